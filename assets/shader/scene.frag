@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec3 frag_normal;
+
+layout(location = 0) out vec4 out_color;
+
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.3, -1.0, 0.5));
+const float AMBIENT = 0.2;
+
+void main() {
+    float diffuse = max(dot(normalize(frag_normal), -LIGHT_DIRECTION), 0.0);
+    out_color = vec4(vec3(AMBIENT + (1.0 - AMBIENT) * diffuse), 1.0);
+}
@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+} push_constants;
+
+layout(location = 0) out vec3 frag_normal;
+
+void main() {
+    gl_Position = push_constants.model * vec4(in_position, 1.0);
+    frag_normal = mat3(push_constants.model) * in_normal;
+}
@@ -1,4 +1,15 @@
-use std::{fs::{self, DirEntry}, path::Path};
+use std::{env, fs::{self, DirEntry}, path::{Path, PathBuf}, thread, time::SystemTime};
+
+use anyhow::Context;
+
+const SHADER_DIR: &'static str = "./assets/shader";
+/// Overrides the shader optimization level with `zero`, `size`, or `performance`.
+const SHADER_OPTIMIZATION_ENV: &'static str = "SIGILL_SHADER_OPTIMIZATION";
+/// Set to `1` in debug builds to write a `.spvasm` disassembly next to each compiled shader, e.g. to attach to bug reports.
+const DUMP_SPVASM_ENV: &'static str = "SIGILL_DUMP_SPVASM";
+/// The options that the shaders in [`SHADER_DIR`] were last compiled with.
+/// This is kept next to the shaders rather than in `OUT_DIR`, since every profile shares the compiled shaders.
+const OPTIONS_STAMP_NAME: &'static str = ".shader_options";
 
 #[allow(unused)]
 macro_rules! p {
@@ -7,34 +18,53 @@ macro_rules! p {
     }
 }
 
+struct ShaderJob {
+    path: PathBuf,
+    shader_kind: shaderc::ShaderKind,
+    target_path: PathBuf,
+}
+
 pub fn main() -> anyhow::Result<()> {
-    let compiler = shaderc::Compiler::new().unwrap();
-    let mut options = shaderc::CompileOptions::new().unwrap();
-    options.set_include_callback(|requested, include_type, source, include_depth| {
-        if include_depth > 127 {
-            return shaderc::IncludeCallbackResult::Err(format!("Maximum include depth reached in {source} including {requested}! Check for recursive include directives."))
-        }
-        if include_type == shaderc::IncludeType::Standard {
-            return shaderc::IncludeCallbackResult::Err(format!("Cannot find requested {requested} from {source}!"))
-        }
-        let source = fs::read_to_string(format!("{source}/../{requested}")).expect(format!("Failed to find {requested} from {source}").as_str()).to_string();
-        Ok(
-            shaderc::ResolvedInclude {
-                resolved_name: requested.to_string(),
-                content: source,
-            }
-        )
-    });
-    let shader_files = recurse_dir("./assets/shader")?;
+    println!("cargo:rerun-if-changed={SHADER_DIR}");
+    println!("cargo:rerun-if-env-changed={SHADER_OPTIMIZATION_ENV}");
+    println!("cargo:rerun-if-env-changed={DUMP_SPVASM_ENV}");
+
+    // Partial checkouts may lack the assets, so don't fail the build over a missing directory.
+    if !Path::new(SHADER_DIR).is_dir() {
+        p!("Shader directory {SHADER_DIR} was not found, so no shaders were compiled. Shaders will fail to load at runtime.");
+        return Ok(())
+    }
+    let shader_files = recurse_dir(SHADER_DIR).with_context(|| format!("failed to read shaders from {SHADER_DIR}"))?;
+
+    // Optimize shaders in release builds, and keep them debuggable otherwise.
+    let release = env::var("PROFILE").is_ok_and(|profile| profile == "release");
+    let optimization = match env::var(SHADER_OPTIMIZATION_ENV) {
+        Ok(optimization) => optimization.to_ascii_lowercase(),
+        Err(_) if release => "performance".to_string(),
+        Err(_) => "zero".to_string(),
+    };
+    if optimization_level(&optimization).is_none() {
+        anyhow::bail!("invalid value for {SHADER_OPTIMIZATION_ENV}: {optimization} (expected zero, size, or performance)");
+    }
+    let generate_debug_info = !release;
+    let dump_spvasm = !release && env::var(DUMP_SPVASM_ENV).is_ok_and(|dump| dump == "1");
+    // Recompile every shader when the options change, since the outputs would otherwise be considered up-to-date.
+    let options_stamp = format!("{optimization} {generate_debug_info} {dump_spvasm}");
+    let options_stamp_path = Path::new(SHADER_DIR).join(OPTIONS_STAMP_NAME);
+    let options_changed = fs::read_to_string(&options_stamp_path).ok().as_deref() != Some(options_stamp.as_str());
 
+    // Shaders may include any other source in the directory, so they're only up-to-date if they're newer than all of them.
+    let mut newest_source = SystemTime::UNIX_EPOCH;
+    let mut jobs = Vec::new();
     for file in shader_files {
         let path = file.path();
         if let Some(file_name) = path.file_name() {
-            if file_name.to_string_lossy().to_string().ends_with(".spv") {
+            let file_name = file_name.to_string_lossy().to_string();
+            if file_name.ends_with(".spv") || file_name.ends_with(".spvasm") || file_name == OPTIONS_STAMP_NAME {
                 continue;
             }
         }
-        let source = fs::read_to_string(path.clone())?;
+        newest_source = newest_source.max(file.metadata()?.modified()?);
         let file_name = path.to_string_lossy().to_string();
         let extension = file_name.split(".").last();
         if extension.is_none() {
@@ -44,20 +74,109 @@ pub fn main() -> anyhow::Result<()> {
         if shader_kind.is_none() {
             continue;
         }
-        let shader_binary = compiler.compile_into_spirv(
+        let target_path = PathBuf::from(format!("{}_{}.spv", path.with_extension("").to_string_lossy().to_string(), extension.unwrap()));
+        jobs.push(ShaderJob { path, shader_kind: shader_kind.unwrap(), target_path });
+    }
+    jobs.retain(|job| {
+        let compiled = fs::metadata(&job.target_path).and_then(|metadata| metadata.modified());
+        options_changed || !compiled.is_ok_and(|compiled| compiled >= newest_source)
+    });
+    if jobs.is_empty() {
+        return Ok(())
+    }
+
+    // Compilers aren't `Sync`, so each thread gets its own.
+    let thread_count = thread::available_parallelism().map_or(1, |count| count.get()).min(jobs.len());
+    let chunk_size = jobs.len().div_ceil(thread_count);
+    let errors = thread::scope(|scope| {
+        let handles = jobs
+            .chunks(chunk_size)
+            .map(|jobs| scope.spawn(|| compile_shaders(jobs, &optimization, generate_debug_info, dump_spvasm)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("shader compilation thread panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    if !errors.is_empty() {
+        let messages = errors.iter().map(|error| format!("{error:#}")).collect::<Vec<_>>();
+        anyhow::bail!("failed to compile {} shader(s):\n{}", errors.len(), messages.join("\n"));
+    }
+    fs::write(&options_stamp_path, options_stamp)?;
+
+    Ok(())
+}
+
+/// Compiles each shader, returning the errors of those that failed.
+fn compile_shaders(jobs: &[ShaderJob], optimization: &str, generate_debug_info: bool, dump_spvasm: bool) -> Vec<anyhow::Error> {
+    let compiler = shaderc::Compiler::new().unwrap();
+    let mut options = compile_options();
+    options.set_optimization_level(optimization_level(optimization).unwrap());
+    if generate_debug_info {
+        options.set_generate_debug_info();
+    }
+    jobs
+        .iter()
+        .filter_map(|job| compile_shader(&compiler, &options, job, dump_spvasm).err())
+        .collect()
+}
+
+fn compile_shader(compiler: &shaderc::Compiler, options: &shaderc::CompileOptions, job: &ShaderJob, dump_spvasm: bool) -> anyhow::Result<()> {
+    let source = fs::read_to_string(&job.path).with_context(|| format!("failed to read shader {}", job.path.display()))?;
+    let file_name = job.path.to_string_lossy().to_string();
+    let shader_binary = compiler.compile_into_spirv(
+        &source,
+        job.shader_kind,
+        &file_name,
+        "main",
+        Some(options),
+    )?;
+    fs::write(&job.target_path, shader_binary.as_binary_u8()).with_context(|| format!("failed to write {}", job.target_path.display()))?;
+    if dump_spvasm {
+        // Disassembly requires a second compile, since shaderc can't disassemble an existing binary.
+        let shader_assembly = compiler.compile_into_spirv_assembly(
             &source,
-            shader_kind.unwrap(),
+            job.shader_kind,
             &file_name,
             "main",
-            Some(&options),
+            Some(options),
         )?;
-        let target_path = &format!("{}_{}.spv", path.with_extension("").to_string_lossy().to_string(), extension.unwrap());
-        fs::write(Path::new(target_path.as_str()), shader_binary.as_binary_u8())?;
+        let assembly_path = job.target_path.with_extension("spvasm");
+        fs::write(&assembly_path, shader_assembly.as_text()).with_context(|| format!("failed to write {}", assembly_path.display()))?;
     }
-
     Ok(())
 }
 
+fn compile_options() -> shaderc::CompileOptions<'static> {
+    let mut options = shaderc::CompileOptions::new().unwrap();
+    options.set_include_callback(|requested, include_type, source, include_depth| {
+        if include_depth > 127 {
+            return shaderc::IncludeCallbackResult::Err(format!("Maximum include depth reached in {source} including {requested}! Check for recursive include directives."))
+        }
+        if include_type == shaderc::IncludeType::Standard {
+            return shaderc::IncludeCallbackResult::Err(format!("Cannot find requested {requested} from {source}!"))
+        }
+        let source = fs::read_to_string(format!("{source}/../{requested}")).expect(format!("Failed to find {requested} from {source}").as_str()).to_string();
+        Ok(
+            shaderc::ResolvedInclude {
+                resolved_name: requested.to_string(),
+                content: source,
+            }
+        )
+    });
+    options
+}
+
+fn optimization_level(optimization: &str) -> Option<shaderc::OptimizationLevel> {
+    match optimization {
+        "zero" => Some(shaderc::OptimizationLevel::Zero),
+        "size" => Some(shaderc::OptimizationLevel::Size),
+        "performance" => Some(shaderc::OptimizationLevel::Performance),
+        _ => None,
+    }
+}
+
 fn extension_to_shader_kind(extension: &str) -> Option<shaderc::ShaderKind> {
     match extension {
         "frag" => Some(shaderc::ShaderKind::Fragment),
@@ -2,47 +2,20 @@ use std::str::FromStr;
 
 use proc_macro::TokenStream;
 use quote::{quote, ToTokens};
-use syn::{parse_macro_input, Data, DeriveInput};
+use syn::{parse_macro_input, Data, DeriveInput, Field, Fields};
 
 #[proc_macro_derive(Deref, attributes(deref))]
 pub fn derive_deref(input: TokenStream) -> TokenStream {
     let DeriveInput { ident, generics, data, .. } = parse_macro_input!(input);
-    let fields;
-    if let Data::Struct(data_struct) = data {
-        fields = data_struct.fields;
-    } else {
-        panic!("Only structs may derive Deref.");
-    }
-
-    let mut field_type = None;
-    let mut field_name = None;
-
-    'f: for (i, field) in fields.iter().enumerate() {
-        for attr in field.attrs.iter() {
-            if attr.path().is_ident("deref") {
-                field_type = Some(field.ty.clone());
-                if let Some(ref ident) = field.ident {
-                    field_name = Some(ident.to_token_stream());
-                } else {
-                    field_name = Some(syn::Index::from(i).to_token_stream());
-                }
-                break 'f;
-            }
-        }
-    }
+    let fields = match data {
+        Data::Struct(data_struct) => data_struct.fields,
+        _ => return syn::Error::new_spanned(&ident, "only structs may derive Deref").to_compile_error().into(),
+    };
 
-    if field_name.is_none() {
-        if let Some(field) = fields.iter().nth(0) {
-            field_type = Some(field.ty.clone());
-            if let Some(ref ident) = field.ident {
-                field_name = Some(ident.to_token_stream());
-            } else {
-                field_name = Some(syn::Index::from(0).to_token_stream());
-            }
-        } else {
-            panic!("No default field or field with #[deref] attribute found.");
-        }
-    }
+    let (field_name, field_type) = match deref_target(&ident, &fields) {
+        Ok(deref_target) => deref_target,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
     let where_clause = if let Some(ref where_clause) = generics.where_clause {
         where_clause.to_token_stream()
@@ -68,39 +41,15 @@ pub fn derive_deref(input: TokenStream) -> TokenStream {
 #[proc_macro_derive(DerefMut)]
 pub fn derive_deref_mut(input: TokenStream) -> TokenStream {
     let DeriveInput { ident, generics, data, .. } = parse_macro_input!(input);
-    let fields;
-    if let Data::Struct(data_struct) = data {
-        fields = data_struct.fields;
-    } else {
-        panic!("Only structs may derive Deref.");
-    }
-
-    let mut field_name = None;
-
-    'f: for (i, field) in fields.iter().enumerate() {
-        for attr in field.attrs.iter() {
-            if attr.path().is_ident("deref") {
-                if let Some(ref ident) = field.ident {
-                    field_name = Some(ident.to_token_stream());
-                } else {
-                    field_name = Some(syn::Index::from(i).to_token_stream());
-                }
-                break 'f;
-            }
-        }
-    }
+    let fields = match data {
+        Data::Struct(data_struct) => data_struct.fields,
+        _ => return syn::Error::new_spanned(&ident, "only structs may derive DerefMut").to_compile_error().into(),
+    };
 
-    if field_name.is_none() {
-        if let Some(field) = fields.iter().nth(0) {
-            if let Some(ref ident) = field.ident {
-                field_name = Some(ident.to_token_stream());
-            } else {
-                field_name = Some(syn::Index::from(0).to_token_stream());
-            }
-        } else {
-            panic!("No default field or field with #[deref] attribute found. Ensure that Deref has been derived first.");
-        }
-    }
+    let (field_name, _) = match deref_target(&ident, &fields) {
+        Ok(deref_target) => deref_target,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
     let where_clause = if let Some(ref where_clause) = generics.where_clause {
         where_clause.to_token_stream()
@@ -120,3 +69,72 @@ pub fn derive_deref_mut(input: TokenStream) -> TokenStream {
 
     output.into()
 }
+
+/// Implements `Debug` by forwarding to the field targeted by `Deref`, so that wrappers are formatted as their inner value.
+#[proc_macro_derive(DerefDebug, attributes(deref))]
+pub fn derive_deref_debug(input: TokenStream) -> TokenStream {
+    let DeriveInput { ident, generics, data, .. } = parse_macro_input!(input);
+    let fields = match data {
+        Data::Struct(data_struct) => data_struct.fields,
+        _ => return syn::Error::new_spanned(&ident, "only structs may derive DerefDebug").to_compile_error().into(),
+    };
+
+    let (field_name, field_type) = match deref_target(&ident, &fields) {
+        Ok(deref_target) => deref_target,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    // Only require the inner field to be `Debug`, rather than every generic parameter.
+    let mut generics = generics;
+    generics.make_where_clause().predicates.push(syn::parse_quote!(#field_type: std::fmt::Debug));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    let output = {
+        quote! {
+            impl #impl_generics std::fmt::Debug for #ident #type_generics #where_clause {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    std::fmt::Debug::fmt(&self.#field_name, f)
+                }
+            }
+        }
+    };
+
+    output.into()
+}
+
+/// Returns the name (or tuple index) and type of the field to dereference to, which is the field marked `#[deref]` or else the first field.
+fn deref_target(ident: &syn::Ident, fields: &Fields) -> syn::Result<(proc_macro2::TokenStream, syn::Type)> {
+    let (i, field) = match find_deref_field(fields)? {
+        Some(deref_field) => deref_field,
+        None => fields
+            .iter()
+            .enumerate()
+            .next()
+            .ok_or_else(|| syn::Error::new_spanned(ident, "no field to dereference to; add a field or mark one with #[deref]"))?,
+    };
+    let field_name = match field.ident {
+        Some(ref ident) => ident.to_token_stream(),
+        None => syn::Index::from(i).to_token_stream(),
+    };
+    Ok((field_name, field.ty.clone()))
+}
+
+/// Finds the field marked `#[deref]`, if any.
+/// Marking more than one field is an error pointing at each extra field, rather than silently using the first.
+fn find_deref_field(fields: &Fields) -> syn::Result<Option<(usize, &Field)>> {
+    let mut marked_fields = fields
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| field.attrs.iter().any(|attr| attr.path().is_ident("deref")));
+    let deref_field = marked_fields.next();
+    let error = marked_fields
+        .map(|(_, field)| syn::Error::new_spanned(field, "only one field may be marked #[deref]"))
+        .reduce(|mut error, next| {
+            error.combine(next);
+            error
+        });
+    match error {
+        Some(error) => Err(error),
+        None => Ok(deref_field),
+    }
+}
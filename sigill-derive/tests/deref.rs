@@ -0,0 +1,39 @@
+use sigill_derive::{Deref, DerefDebug, DerefMut};
+
+#[derive(Debug, PartialEq)]
+struct Inner {
+    value: u32,
+    name: &'static str,
+}
+
+#[derive(DerefDebug)]
+struct Wrapper(Inner);
+
+#[derive(Deref, DerefMut, DerefDebug)]
+struct Marked {
+    _id: u32,
+    #[deref]
+    inner: Vec<u32>,
+}
+
+#[test]
+fn deref_debug_formats_as_inner_value() {
+    let inner = Inner { value: 7, name: "seven" };
+    let expected = format!("{inner:?}");
+    assert_eq!(format!("{:?}", Wrapper(inner)), expected);
+}
+
+#[test]
+fn deref_debug_respects_alternate_formatting() {
+    let inner = Inner { value: 7, name: "seven" };
+    let expected = format!("{inner:#?}");
+    assert_eq!(format!("{:#?}", Wrapper(inner)), expected);
+}
+
+#[test]
+fn deref_targets_marked_field() {
+    let mut marked = Marked { _id: 1, inner: vec![1, 2] };
+    marked.push(3);
+    assert_eq!(*marked, [1, 2, 3]);
+    assert_eq!(format!("{marked:?}"), "[1, 2, 3]");
+}
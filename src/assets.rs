@@ -0,0 +1,63 @@
+//! # Assets
+//! Resolves the paths of assets at runtime, since the working directory may differ from the asset root.
+//!
+//! The asset root is [`ASSETS_ENV`] if it is set, or otherwise the first [`constants::ASSET_DIR`] found next to the executable,
+//! in one of its ancestors (e.g. the crate root when running from `target/debug`), or in the working directory.
+
+use std::{env, io, path::{Path, PathBuf}};
+
+use thiserror::Error;
+
+use crate::constants;
+
+/// Overrides the asset root.
+pub const ASSETS_ENV: &'static str = "SIGILL_ASSETS";
+
+#[derive(Error, Debug)]
+pub enum AssetError {
+    #[error("asset directory not found (set {ASSETS_ENV} to its location)")]
+    RootNotFound,
+    #[error("asset not found: {0}")]
+    NotFound(PathBuf),
+    #[error("invalid shader name `{0}` (expected a name with a stage extension, e.g. `triangle.vert`)")]
+    InvalidShaderName(String),
+    #[error("I/O Error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+pub type AssetResult<T> = Result<T, AssetError>;
+
+/// Finds the asset root.
+pub fn asset_root() -> AssetResult<PathBuf> {
+    if let Some(root) = env::var_os(ASSETS_ENV) {
+        let root = PathBuf::from(root);
+        return if root.is_dir() { Ok(root) } else { Err(AssetError::NotFound(root)) }
+    }
+
+    let executable = env::current_exe()?;
+    executable
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(constants::ASSET_DIR))
+        .chain(std::iter::once(env::current_dir()?.join(constants::ASSET_DIR)))
+        .find(|root| root.is_dir())
+        .ok_or(AssetError::RootNotFound)
+}
+
+/// Resolves the path of an asset relative to the asset root, failing if it doesn't exist.
+pub fn resolve(path: impl AsRef<Path>) -> AssetResult<PathBuf> {
+    let path = asset_root()?.join(path);
+    if !path.exists() {
+        return Err(AssetError::NotFound(path))
+    }
+    Ok(path)
+}
+
+/// Resolves the compiled SPIR-V of a shader from its source name relative to the shader directory,
+/// e.g. `triangle.vert` resolves to `shader/triangle_vert.spv` as output by the build script.
+pub fn resolve_shader(name: &str) -> AssetResult<PathBuf> {
+    let Some((stem, stage)) = name.rsplit_once('.') else {
+        return Err(AssetError::InvalidShaderName(name.to_string()))
+    };
+    resolve(Path::new(constants::SHADER_ASSET_DIR).join(format!("{stem}_{stage}.spv")))
+}
@@ -1,4 +1,8 @@
-use rendering::RenderData;
+use std::{fmt::Display, time::Instant};
+
+use rendering::{AppInfo, RenderData};
+
+use crate::{constants, error};
 
 pub mod rendering;
 
@@ -6,4 +10,35 @@ pub struct ClientData {
     pub window: Option<winit::window::Window>,
     pub attributes: winit::window::WindowAttributes,
     pub render_data: Option<RenderData>,
+    pub app_info: AppInfo,
+    /// When the window was last resized, if the swapchain has yet to be recreated to match.
+    pub pending_resize: Option<Instant>,
+    /// Whether a fatal error occurred and the app is exiting, in which case the process exits with an error code.
+    pub failed: bool,
+}
+
+/// When a fatal error occurred, which determines how it is described to the user.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FatalErrorPhase {
+    /// While the app was starting up, before it began running.
+    Startup,
+    /// While the app was running.
+    Runtime,
+}
+
+/// Logs a fatal error and shows it in a native message box, so that users without a terminal know why the app closed.
+/// This blocks until the message box is dismissed.
+pub fn show_fatal_error(phase: FatalErrorPhase, context: &str, error: &dyn Display) {
+    error!("{context}: {error}");
+    let summary = match phase {
+        FatalErrorPhase::Startup => "failed to start",
+        FatalErrorPhase::Runtime => "stopped unexpectedly",
+    };
+    let description = format!("{} {summary}.\n\n{context}: {error}\n\nPlease report this on our issue tracker: {}", constants::NAME, constants::ISSUE_TRACKER);
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title(format!("{} has encountered a fatal error", constants::NAME))
+        .set_description(description)
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
 }
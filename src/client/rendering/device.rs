@@ -7,6 +7,7 @@ use ash::vk::{self, QueueFlags};
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
 use super::{constants, vulkan, App, RenderError, RenderResult};
+use crate::{info, settings::PowerPreference};
 
 pub struct RankedDevice(u32, vk::PhysicalDevice);
 
@@ -32,23 +33,31 @@ impl Ord for RankedDevice {
 
 /// Select the most suitable device for rendering.
 pub fn find_suitable_device<'a>(instance: &mut vulkan::Instance, app: &App) -> RenderResult<(vk::PhysicalDevice, vulkan::swapchain::SwapchainSupport)> {
-    let physical_devices = instance.enumerate_physical_devices()?;
-    for physical_device in physical_devices.iter() {
-        let supported = check_device_capabilities(instance, *physical_device, app).expect("failed to check device capabilities");
-        if supported {
-            break
+    // Reject devices that don't meet the basic requirements.
+    let mut physical_devices = instance.enumerate_physical_devices()?;
+    let mut supported_devices = Vec::new();
+    for physical_device in physical_devices.drain(..) {
+        if check_device_capabilities(instance, physical_device, app)? {
+            supported_devices.push(physical_device);
         }
     }
 
-    let mut physical_devices = physical_devices
+    let power_preference = app.settings().power_preference;
+    let mut physical_devices = supported_devices
         .into_iter()
-        .map(|physical_device| RankedDevice(rank_device_capabilities(&instance, physical_device), physical_device))
+        .map(|physical_device| RankedDevice(rank_device_capabilities(&instance, physical_device, power_preference), physical_device))
         .collect::<Vec<RankedDevice>>();
     physical_devices.sort();
 
-    let suitable_device = physical_devices.last();
+    // Prefer the user's GPU of choice over the highest-ranked one.
+    let preferred_device = app.settings().preferred_gpu.as_ref().and_then(|preferred_gpu| {
+        let preferred_gpu = preferred_gpu.to_lowercase();
+        physical_devices.iter().find(|device| device_name(instance, device.1).to_lowercase().contains(&preferred_gpu))
+    });
+    let suitable_device = preferred_device.or(physical_devices.last());
     if let Some(suitable_device) = suitable_device {
         let suitable_device = suitable_device.1;
+        info!("Selected {} with power preference {power_preference:?}", device_name(instance, suitable_device));
         instance.create_surface(app.window().display_handle()?.as_raw(), app.window().window_handle()?.as_raw())?;
         let swapchain_support = vulkan::swapchain::SwapchainSupport::query(&instance, suitable_device)?;
 
@@ -58,15 +67,25 @@ pub fn find_suitable_device<'a>(instance: &mut vulkan::Instance, app: &App) -> R
     }
 }
 
+/// Returns the human-readable name of the device.
+pub fn device_name(instance: &vulkan::Instance, physical_device: vk::PhysicalDevice) -> String {
+    let properties = instance.get_physical_device_properties(physical_device);
+    properties.device_name_as_c_str().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+}
+
 /// Ensures that the device meets basic requirements.
 pub fn check_device_capabilities(instance: &mut vulkan::Instance, physical_device: vk::PhysicalDevice, app: &App) -> RenderResult<bool> {
     let properties = instance.get_physical_device_properties(physical_device);
     let supported_gpu = properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU || properties.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU;
-    let supports_vulkan_version = vk::api_version_major(properties.api_version) == constants::API_VERSION_MAJOR || vk::api_version_minor(properties.api_version) >= constants::API_VERSION_MINOR;
-
-    let features = instance.get_physical_device_features(physical_device);
-    let supports_geometry_shader = features.geometry_shader == vk::TRUE;
-    let supports_required_features = supports_geometry_shader;
+    // The packed version orders by major, then minor, then patch, so this rejects devices older than the renderer's API version.
+    let supports_vulkan_version = properties.api_version >= constants::API_VERSION;
+
+    let features = vulkan::features::DeviceFeatures::query(instance, physical_device);
+    let required_features = vulkan::features::DeviceFeatures::required();
+    let supports_required_features = features.supports(&required_features);
+    if !supports_required_features {
+        info!("Device {} lacks required features: {}", device_name(instance, physical_device), features.unsupported(&required_features).join(", "));
+    }
 
     let mut available_queue_families = QueueFlags::empty();
     let queue_families = instance.get_physical_device_queue_family_properties(physical_device);
@@ -76,11 +95,8 @@ pub fn check_device_capabilities(instance: &mut vulkan::Instance, physical_devic
     let has_required_queue_families = available_queue_families.contains(*constants::REQUIRED_QUEUE_FAMILIES);
 
     let available_extensions = instance.enumerate_device_extension_properties(physical_device)?;
-    let mut required_extensions: HashSet<String, RandomState> = HashSet::from_iter(constants::ENABLED_EXTENSIONS.iter().map(|&ptr| {
-        // SAFETY: The extension names are guaranteed to be valid C strings.
-        unsafe { CStr::from_ptr(ptr) }.to_string_lossy().to_string()
-    }));
-    required_extensions.extend(constants::ENABLED_DEVICE_EXTENSIONS.iter().map(|&ptr| {
+    // Only device extensions are reported by the device, since instance extensions are checked upon creating the instance.
+    let mut required_extensions: HashSet<String, RandomState> = HashSet::from_iter(constants::ENABLED_DEVICE_EXTENSIONS.iter().map(|&ptr| {
         // SAFETY: The extension names are guaranteed to be valid C strings.
         unsafe { CStr::from_ptr(ptr) }.to_string_lossy().to_string()
     }));
@@ -100,12 +116,16 @@ pub fn check_device_capabilities(instance: &mut vulkan::Instance, physical_devic
 }
 
 /// Rank the device based on its capabilities.
-pub fn rank_device_capabilities(instance: &vulkan::Instance, physical_device: vk::PhysicalDevice) -> u32 {
+pub fn rank_device_capabilities(instance: &vulkan::Instance, physical_device: vk::PhysicalDevice, power_preference: PowerPreference) -> u32 {
     let mut score = 0u32;
 
     let properties = instance.get_physical_device_properties(physical_device);
-    // Prefer dedicated GPUs
-    if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
+    // Prefer dedicated GPUs, or integrated GPUs if saving power.
+    let preferred_device_type = match power_preference {
+        PowerPreference::HighPerformance => vk::PhysicalDeviceType::DISCRETE_GPU,
+        PowerPreference::LowPower => vk::PhysicalDeviceType::INTEGRATED_GPU,
+    };
+    if properties.device_type == preferred_device_type {
         score += 1000;
     }
     // Prefer higher maximum image dimensions since those affect graphics quality.
@@ -29,7 +29,7 @@ unsafe extern "system" fn vulkan_debug_callback(
 
     let severity = severity_from_flags(&severity_flags);
     // Don't report severity levels higher than allowed.
-    if severity > constants::LOG_LEVEL {
+    if severity > log::max_level() {
         return vk::FALSE
     }
 
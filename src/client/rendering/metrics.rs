@@ -0,0 +1,115 @@
+//! # Frame Metrics
+//! CPU-side frame timings, which help tell apart CPU-bound and GPU-bound stalls.
+//!
+//! Long fence waits mean that the CPU is waiting on the GPU (i.e. GPU-bound),
+//! whereas long recording times with short fence waits mean that the GPU is waiting on the CPU (i.e. CPU-bound).
+
+use std::time::{Duration, Instant};
+
+use crate::{constants, debug};
+
+/// The CPU-side timings of a frame.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FrameMetrics {
+    /// The time spent waiting for the GPU to finish the last frame that used this frame's resources.
+    pub fence_wait: Duration,
+    /// The time spent waiting to acquire a swapchain image.
+    pub acquire: Duration,
+    /// The time spent recording and submitting command buffers.
+    pub record: Duration,
+    /// The wall time since the start of the previous frame.
+    pub frame_time: Duration,
+}
+
+impl FrameMetrics {
+    fn add(&mut self, other: &Self) {
+        self.fence_wait += other.fence_wait;
+        self.acquire += other.acquire;
+        self.record += other.record;
+        self.frame_time += other.frame_time;
+    }
+
+    fn div(&self, frames: u32) -> Self {
+        Self {
+            fence_wait: self.fence_wait / frames,
+            acquire: self.acquire / frames,
+            record: self.record / frames,
+            frame_time: self.frame_time / frames,
+        }
+    }
+}
+
+/// Measures the timings of each frame and periodically logs their averages.
+pub struct FrameTimer {
+    current: FrameMetrics,
+    last: FrameMetrics,
+    frame_start: Option<Instant>,
+    record_start: Instant,
+    accumulated: FrameMetrics,
+    accumulated_frames: u32,
+    last_report: Instant,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            current: FrameMetrics::default(),
+            last: FrameMetrics::default(),
+            frame_start: None,
+            record_start: now,
+            accumulated: FrameMetrics::default(),
+            accumulated_frames: 0,
+            last_report: now,
+        }
+    }
+
+    /// The timings of the most recently completed frame.
+    #[inline]
+    pub fn last(&self) -> &FrameMetrics {
+        &self.last
+    }
+
+    /// Marks the start of a frame, before waiting on its fence.
+    pub(super) fn begin_frame(&mut self) {
+        let now = Instant::now();
+        self.current = FrameMetrics::default();
+        if let Some(frame_start) = self.frame_start.replace(now) {
+            self.current.frame_time = now - frame_start;
+        }
+    }
+
+    /// Marks the end of the fence wait.
+    pub(super) fn fence_waited(&mut self) {
+        self.current.fence_wait = self.elapsed_since_frame_start();
+    }
+
+    /// Marks the acquisition of a swapchain image, after which recording begins.
+    pub(super) fn image_acquired(&mut self) {
+        self.current.acquire = self.elapsed_since_frame_start() - self.current.fence_wait;
+        self.record_start = Instant::now();
+    }
+
+    /// Marks the end of the frame once it has been submitted.
+    pub(super) fn end_frame(&mut self) {
+        self.current.record = self.record_start.elapsed();
+        self.last = self.current;
+        self.accumulated.add(&self.current);
+        self.accumulated_frames += 1;
+
+        if self.last_report.elapsed() >= constants::FRAME_METRICS_INTERVAL {
+            let average = self.accumulated.div(self.accumulated_frames);
+            debug!(
+                "Average CPU frame timings over {} frames: frame time {:?}, fence wait {:?}, acquire {:?}, record {:?}",
+                self.accumulated_frames, average.frame_time, average.fence_wait, average.acquire, average.record,
+            );
+            self.accumulated = FrameMetrics::default();
+            self.accumulated_frames = 0;
+            self.last_report = Instant::now();
+        }
+    }
+
+    fn elapsed_since_frame_start(&self) -> Duration {
+        self.frame_start.map_or(Duration::ZERO, |frame_start| frame_start.elapsed())
+    }
+}
@@ -1,22 +1,225 @@
-use std::{ffi::CStr, ops::Deref};
+use std::{ffi::{c_char, CStr, CString}, ops::Deref, time::{Duration, Instant}};
 
 use ash::vk;
 use thiserror::Error;
-use winit::{event_loop::ActiveEventLoop, raw_window_handle::{HandleError, HasDisplayHandle}};
+use winit::{dpi::PhysicalSize, event_loop::ActiveEventLoop, raw_window_handle::{HandleError, HasDisplayHandle}};
 
 use crate::*;
 
 pub mod vulkan;
 pub mod log;
 pub mod device;
+pub mod overlay;
+pub mod metrics;
+pub mod scene;
 
 #[allow(unused)]
 pub struct RenderData {
     pub queue_families: vulkan::queues::QueueFamilies,
     pub selected_physical_device: vk::PhysicalDevice,
+    /// The properties of the selected physical device, cached so that they aren't re-queried from the driver.
+    pub properties: vk::PhysicalDeviceProperties,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+    /// The device features enabled at device creation.
+    pub enabled_features: vulkan::features::DeviceFeatures,
+    /// The surface format and present mode selected during initialization, which are reused when recreating the swapchain.
+    pub surface_format: vk::SurfaceFormatKHR,
+    pub present_mode: vk::PresentModeKHR,
+    /// The surface's swapchain support, cached so that recreating the swapchain only re-queries its capabilities.
+    pub swapchain_support: vulkan::swapchain::SwapchainSupport,
+    /// How the draw image's contents from the previous frame are treated at the start of each frame.
+    /// Use [`vk::AttachmentLoadOp::LOAD`] to accumulate effects across frames.
+    pub draw_image_load_op: vk::AttachmentLoadOp,
+    /// The color that the draw image is cleared to if its load op is [`vk::AttachmentLoadOp::CLEAR`], or a flashing background if unset.
+    pub draw_image_clear_color: Option<vk::ClearColorValue>,
+    /// The timeout of waits on the GPU before they're retried, in nanoseconds.
+    pub fence_timeout: u64,
+    /// How far the current frame is between the last game tick and the next, from 0 to 1.
+    pub interpolation_alpha: f32,
+    /// Where each frame is rendered to.
+    pub render_path: RenderPath,
+    /// The index of the swapchain image acquired for the current frame.
+    pub swapchain_image_index: u32,
+    pub frame_timer: metrics::FrameTimer,
+    /// Overlays drawn on top of the scene each frame, in order.
+    pub overlays: Vec<Box<dyn overlay::RenderOverlay>>,
+    /// The staging pool that meshes are uploaded through, which keeps their buffers alive until their uploads finish.
+    pub staging_pool: vulkan::buffer::StagingPool,
+    /// The meshes referenced by [`scene::MeshHandle`]s, which must be dropped before the instance.
+    pub meshes: Vec<scene::Mesh>,
+    /// The pipeline that renderable entities are drawn with, without which the scene isn't drawn.
+    pub scene_pipeline: Option<scene::ScenePipeline>,
+    /// Whether to draw the scene in wireframe, if the scene pipeline has a wireframe variant. See [`Self::toggle_wireframe`].
+    pub wireframe: bool,
+    /// Whether rendering is paused, e.g. while the window is hidden. See [`Self::set_paused`].
+    paused: bool,
     pub instance: vulkan::Instance,
 }
 
+impl RenderData {
+    /// Pauses or resumes rendering. [`render`] skips frames while paused without requesting redraws,
+    /// so a redraw must be requested upon resuming to restart the render loop.
+    #[inline]
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Toggles wireframe rendering, unless the device lacks the `fill_mode_non_solid` feature that it requires.
+    pub fn toggle_wireframe(&mut self) {
+        if self.enabled_features.core.fill_mode_non_solid != vk::TRUE {
+            warn!("Wireframe rendering is unavailable, since the device doesn't support fill_mode_non_solid");
+            return
+        }
+        self.wireframe = !self.wireframe;
+        info!("Wireframe rendering {}", if self.wireframe { "enabled" } else { "disabled" });
+    }
+
+    #[inline]
+    pub fn properties(&self) -> &vk::PhysicalDeviceProperties {
+        &self.properties
+    }
+
+    #[inline]
+    pub fn memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
+        &self.memory_properties
+    }
+
+    #[inline]
+    pub fn limits(&self) -> &vk::PhysicalDeviceLimits {
+        &self.properties.limits
+    }
+
+    #[inline]
+    pub fn queue_families(&self) -> &vulkan::queues::QueueFamilies {
+        &self.queue_families
+    }
+
+    /// The raw handle of the graphics queue.
+    #[inline]
+    pub fn graphics_queue(&self) -> vk::Queue {
+        self.queue_families.queue_handle(vulkan::queues::QueueType::Graphics).expect("graphics queue handle should be populated during initialization")
+    }
+
+    #[inline]
+    pub fn instance_handle(&self) -> &ash::Instance {
+        self.instance.handle()
+    }
+
+    #[inline]
+    pub fn device_handle(&self) -> &ash::Device {
+        self.instance.device().handle()
+    }
+
+    #[inline]
+    pub fn draw_image_format(&self) -> vk::Format {
+        self.instance.draw_image().format()
+    }
+
+    /// The format of the image that each frame is rendered to, which overlays render to.
+    #[inline]
+    pub fn render_target_format(&self) -> vk::Format {
+        match self.render_path {
+            RenderPath::DrawImage => self.draw_image_format(),
+            RenderPath::Swapchain => self.instance.swapchain().format(),
+        }
+    }
+
+    /// The number of frames that may be in flight at once, which overlays should size their per-frame resources by.
+    #[inline]
+    pub const fn frames_in_flight(&self) -> usize {
+        constants::FRAMEBUFFER_SIZE
+    }
+
+    pub fn add_overlay(&mut self, overlay: Box<dyn overlay::RenderOverlay>) {
+        self.overlays.push(overlay);
+    }
+
+    /// Uploads a mesh for [`scene::Renderable`]s to reference via the staging pool, without waiting for the upload to finish.
+    pub fn add_mesh(&mut self, vertices: &[scene::Vertex], indices: &[u32]) -> RenderResult<scene::MeshHandle> {
+        let mesh = scene::Mesh::new(self.instance.device(), self.queue_families.graphics(), &mut self.staging_pool, vertices, indices)?;
+        self.meshes.push(mesh);
+        Ok(scene::MeshHandle(self.meshes.len() - 1))
+    }
+
+    /// The CPU-side timings of the most recently completed frame.
+    #[inline]
+    pub fn frame_metrics(&self) -> &metrics::FrameMetrics {
+        self.frame_timer.last()
+    }
+
+    #[inline]
+    pub fn enabled_features(&self) -> &vulkan::features::DeviceFeatures {
+        &self.enabled_features
+    }
+
+    /// Creates a sampler using `filter`, with anisotropic filtering as configured in the settings.
+    pub fn create_sampler(&self, settings: &Settings, filter: vk::Filter) -> RenderResult<vulkan::Sampler> {
+        let create_info = vulkan::util::sampler_create_info(filter, settings.max_anisotropy, self.limits(), &self.enabled_features);
+        Ok(self.instance.device().create_sampler(&create_info)?)
+    }
+
+    /// Tears down rendering once the GPU has finished its work, destroying all Vulkan objects in order of dependency.
+    pub fn shutdown(self) {
+        if let Err(e) = self.instance.device().wait_idle() {
+            error!("Failed to wait for the device to idle before shutting down: {e}");
+        }
+        // The staging pool may still own buffers of in-flight uploads, so it must be dropped before the instance too.
+        drop(self.staging_pool);
+        drop(self.meshes);
+        drop(self.scene_pipeline);
+        drop(self.instance);
+        info!("Rendering has shut down.");
+    }
+}
+
+impl std::fmt::Debug for RenderData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderData")
+            .field("queue_families", &self.queue_families)
+            .field("selected_physical_device", &self.selected_physical_device)
+            .field("surface_format", &self.surface_format)
+            .field("present_mode", &self.present_mode)
+            .field("swapchain_support", &self.swapchain_support)
+            .field("draw_image_load_op", &self.draw_image_load_op)
+            .field("render_path", &self.render_path)
+            .field("swapchain_image_index", &self.swapchain_image_index)
+            .field("frame_metrics", self.frame_metrics())
+            .field("overlays", &self.overlays.len())
+            .field("meshes", &self.meshes.len())
+            .field("scene_pipeline", &self.scene_pipeline)
+            .field("instance", &self.instance)
+            .finish_non_exhaustive()
+    }
+}
+
+pub use crate::settings::RenderPath;
+
+/// The application identity reported to drivers and tools like RenderDoc.
+/// Embedders may override this to report their own identity; it defaults to SIGILL's.
+#[derive(Clone, Debug)]
+pub struct AppInfo {
+    pub name: CString,
+    pub version: u32,
+    pub engine_name: CString,
+    pub engine_version: u32,
+}
+
+impl Default for AppInfo {
+    fn default() -> Self {
+        Self {
+            name: constants::C_NAME.clone(),
+            version: constants::VERSION,
+            engine_name: constants::C_NAME.clone(),
+            engine_version: constants::ENGINE_VERSION,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum RenderError {
     #[error("error initializing renderer: {0}")]
@@ -29,13 +232,49 @@ pub enum RenderError {
     ValidationLayerNotFound(String),
     #[error("no supported graphics devices were found")]
     UnsupportedDevice,
+    #[error("no queue family supports presenting to the window surface")]
+    NoPresentQueue,
+    #[error("none of the present modes {requested:?} are supported by the surface (supported: {available:?})")]
+    NoSupportedPresentMode {
+        requested: Vec<vk::PresentModeKHR>,
+        available: Vec<vk::PresentModeKHR>,
+    },
+    #[error("the {0:?} queue was not requested at device creation")]
+    QueueUnavailable(vulkan::queues::QueueType),
+    #[error("the {queue_type:?} queue has index {queue_index} in queue family {queue_family_index}, which only has {queue_count} queue(s) requested")]
+    QueueIndexOutOfRange {
+        queue_type: vulkan::queues::QueueType,
+        queue_family_index: vulkan::QueueFamilyIndex,
+        queue_index: vulkan::QueueIndex,
+        queue_count: u32,
+    },
     #[error("I/O Error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("error loading asset: {0}")]
+    AssetError(#[from] crate::assets::AssetError),
+    #[error("compute group size {0:?} exceeds the device limit of {1:?}")]
+    UnsupportedComputeGroupSize([u32; 3], [u32; 3]),
+    #[error("no memory type supports usage {usage:?} with required flags {required_flags:?}: {result}")]
+    NoSuitableMemoryType {
+        result: vk::Result,
+        usage: vk_mem::MemoryUsage,
+        required_flags: vk::MemoryPropertyFlags,
+    },
+    /// VMA reports errors as plain Vulkan results, so this distinguishes allocator failures from other Vulkan errors.
+    #[error("VMA failed to allocate memory with usage {usage:?}: {result}")]
+    Allocation {
+        result: vk::Result,
+        usage: vk_mem::MemoryUsage,
+    },
+    #[error("error reflecting shader: {0}")]
+    ShaderReflectionError(String),
+    #[error("shader {0} has not been loaded")]
+    ShaderNotLoaded(String),
 }
 
 pub type RenderResult<T> = Result<T, RenderError>;
 
-pub fn init(app: &mut App, event_loop: &ActiveEventLoop) -> RenderResult<()> {
+pub fn init(app: &mut App, event_loop: &ActiveEventLoop, app_info: &AppInfo) -> RenderResult<()> {
     warn!("Now loading Vulkan library. If the game crashes after this warning, check to see if your system supports Vulkan!");
     // SAFETY: ¯\_(ツ)_/¯
     // Beware of garbage error messages on UNIX-likes, since `dlerror` is not MT-safe.
@@ -44,23 +283,37 @@ pub fn init(app: &mut App, event_loop: &ActiveEventLoop) -> RenderResult<()> {
     let entry = unsafe { ash::Entry::load()? };
     info!("Vulkan has loaded.");
     
-    let app_name = &*constants::C_NAME;
     let app_info = vk::ApplicationInfo::default()
-        .application_name(app_name)
-        .application_version(constants::VERSION)
-        .engine_name(app_name)
-        .engine_version(constants::ENGINE_VERSION)
+        .application_name(&app_info.name)
+        .application_version(app_info.version)
+        .engine_name(&app_info.engine_name)
+        .engine_version(app_info.engine_version)
         .api_version(constants::API_VERSION);
 
     // Get required extensions
     let mut extensions = ash_window::enumerate_required_extensions(event_loop.display_handle()?.as_raw())?.to_vec();
     extensions.extend_from_slice(constants::ENABLED_EXTENSIONS);
 
+    // SAFETY: The entry is loaded at this point.
+    let available_extensions = unsafe { entry.enumerate_instance_extension_properties(None)? };
+
+    // HDR color spaces require an additional extension, so fall back to SDR if it isn't available.
+    let hdr = app.settings().hdr && available_extensions.iter().any(|extension| extension.extension_name_as_c_str() == Ok(ash::ext::swapchain_colorspace::NAME));
+    if app.settings().hdr && !hdr {
+        warn!("HDR output was requested, but {} is unavailable.", ash::ext::swapchain_colorspace::NAME.to_string_lossy());
+    }
+    if hdr {
+        extensions.push(ash::ext::swapchain_colorspace::NAME.as_ptr());
+    }
+
+    log_extensions("instance", &extensions, &available_extensions);
+
     // Create instance
     let mut instance_info = vk::InstanceCreateInfo::default()
         .application_info(&app_info)
         .enabled_extension_names(&extensions);
-    if constants::ENABLE_VALIDATION_LAYERS {
+    let mut enable_validation_layers = app.settings().enable_validation_layers;
+    if enable_validation_layers {
         // Ensure the required validation layers are available.
         let available_layers = unsafe { entry.enumerate_instance_layer_properties()? };
         
@@ -71,39 +324,179 @@ pub fn init(app: &mut App, event_loop: &ActiveEventLoop) -> RenderResult<()> {
             if available_layers.iter().find(|layer| {
                 layer.layer_name_as_c_str().unwrap().eq(required_validation_layer)
             }).is_none() {
+                // Safe mode is a recovery path, so it only enables validation if the layers are installed (e.g. with the Vulkan SDK).
+                if app.settings().safe_mode {
+                    warn!("Validation layer {} was not found, so safe mode continues without validation.", required_validation_layer.to_string_lossy());
+                    enable_validation_layers = false;
+                    break
+                }
                 return Err(RenderError::ValidationLayerNotFound(required_validation_layer.to_string_lossy().to_string()))
             }
         }
-        
+    }
+    info!("Validation layers are {}.", if enable_validation_layers { "enabled" } else { "disabled" });
+    if enable_validation_layers {
         instance_info = instance_info.enabled_layer_names(constants::REQUIRED_VALIDATION_LAYERS);
     }
     let mut instance = vulkan::Instance::new(entry, &instance_info)?;
 
-    if cfg!(debug_assertions) {
+    if enable_validation_layers {
         // Set up debugging
         log::init_vulkan_debug_callback(&mut instance)?;
     }
 
     // Find a suitable physical device and create window surface.
+    instance.log_physical_devices()?;
     let (selected_physical_device, swapchain_support) = device::find_suitable_device(&mut instance, app)?;
+    log_extensions("device", constants::ENABLED_DEVICE_EXTENSIONS, &instance.enumerate_device_extension_properties(selected_physical_device)?);
 
-    // Extract swapchain capabilities.
-    let capabilities = swapchain_support.capabilities();
-    let format = swapchain_support.select_format();
+    let preferred_formats = if hdr { constants::HDR_SURFACE_FORMATS } else { constants::SURFACE_FORMATS };
+    let format = *swapchain_support.select_format(preferred_formats);
+    info!("Using surface format {:?} with color space {:?}", format.format, format.color_space);
 
     // Get queue families for use during device creation.
     let queue_flags = *constants::QUEUE_FAMILIES;
-    let queue_family_map = instance.get_queue_family_map(selected_physical_device, queue_flags);
+    let queue_family_map = instance.get_queue_family_map(selected_physical_device, queue_flags, vulkan::queues::QueueSharing::default());
     debug!("Queue Families queried: {queue_family_map:?}");
     let mut queue_families = vulkan::queues::QueueFamilies::new_empty(&queue_family_map);
     queue_families = queue_families.query_present_mode_queue(&queue_family_map, &instance, selected_physical_device, instance.surface())?;
+    queue_families = queue_families.request_additional_queues(vulkan::queues::QueueType::Graphics, constants::ADDITIONAL_GRAPHICS_QUEUE_PRIORITIES, &instance, selected_physical_device)?;
     trace!("Using Queue Families: {queue_families:#?}");
 
-    // Create swapchain info.
-    let image_extent = swapchain_support.select_extent(app.window().inner_size().width, app.window().inner_size().height);
+    let present_mode = swapchain_support.select_present_mode_ex(app.settings().present_mode.preferences())?;
+    trace!("Present mode: {present_mode:?}");
+
+    // Get queue creation info.
+    let queue_create_infos = queue_families.get_queue_create_infos(&queue_family_map)?;
+    trace!("Queue Creation Info: {queue_create_infos:?}");
+
+    // Enable device features, including Vulkan 1.3 features such as Synchronization2 and dynamic rendering.
+    // Devices lacking these features have already been rejected during device selection.
+    let mut enabled_features = vulkan::features::DeviceFeatures::required();
+    // Anisotropic filtering is optional, since samplers disable it when the feature is unavailable.
+    let supported_features = vulkan::features::DeviceFeatures::query(&instance, selected_physical_device);
+    enabled_features.core.sampler_anisotropy = supported_features.core.sampler_anisotropy;
+    // Wireframe rendering is a debugging aid, so it's only available if supported.
+    enabled_features.core.fill_mode_non_solid = supported_features.core.fill_mode_non_solid;
+    if supported_features.core.fill_mode_non_solid != vk::TRUE {
+        debug!("fill_mode_non_solid is unsupported, so wireframe rendering is unavailable");
+    }
+    let frame_sync = if !app.settings().timeline_semaphores {
+        vulkan::commands::FrameSync::Binary
+    } else if supported_features.timeline_semaphore.timeline_semaphore == vk::TRUE {
+        enabled_features.timeline_semaphore.timeline_semaphore = vk::TRUE;
+        vulkan::commands::FrameSync::Timeline
+    } else {
+        warn!("Timeline semaphores are unsupported, so falling back to fences");
+        vulkan::commands::FrameSync::Binary
+    };
+    debug!("Frame synchronization: {frame_sync:?}");
+    let mut enabled_features2 = enabled_features.features2();
+    // Create device.
+    // don't enable device-specific layers because we don't support shitty Vulkan implementations
+    let device_create_info = vk::DeviceCreateInfo::default()
+        .enabled_extension_names(constants::ENABLED_DEVICE_EXTENSIONS)
+        .queue_create_infos(queue_create_infos.as_slice())
+        .push_next(&mut enabled_features2);
+    instance.create_device(selected_physical_device, &device_create_info)?;
+
+    // Create swapchain.
+    let image_extent = create_swapchain(&mut instance, &queue_families, &swapchain_support, format, present_mode, app.window().inner_size())?;
+
+    // Acquiring more images than the swapchain holds would stall frame pacing.
+    let swapchain_image_count = instance.swapchain().image_count();
+    if constants::FRAMEBUFFER_SIZE > swapchain_image_count {
+        warn!("{} frames in flight exceeds the swapchain image count of {swapchain_image_count}", constants::FRAMEBUFFER_SIZE);
+    }
+
+    // Populate Queue handles.
+    queue_families.populate_handles(instance.device());
+    debug!("Queues:{}", queue_families.summarize());
+
+    let properties = instance.get_physical_device_properties(selected_physical_device);
+    let memory_properties = instance.get_physical_device_memory_properties(selected_physical_device);
+
+    instance.create_pipeline_cache(&properties, constants::PIPELINE_CACHE_PATH.into())?;
+    // Compute pipelines are dispatched with `COMPUTE_GROUP_SIZE`, so reject devices that can't run them before creating any.
+    vulkan::pipeline::Pipeline::validate_compute_group_size(&properties.limits, constants::COMPUTE_GROUP_SIZE)?;
+
+    instance.create_framebuffer(
+        vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+        queue_families.graphics().queue_info().0,
+        frame_sync,
+    )?;
+
+    let draw_image_extent = scale_extent(image_extent, app.settings().render_scale());
+    info!("Rendering at {}x{} for a {}x{} window", draw_image_extent.width, draw_image_extent.height, image_extent.width, image_extent.height);
+    create_draw_image(&mut instance, draw_image_extent)?;
+
+    // Missing shaders only break the pipelines that use them, so don't fail initialization over them.
+    for name in constants::PRELOADED_SHADERS {
+        if let Err(e) = instance.load_shader(name) {
+            warn!("Failed to load shader {name}: {e}");
+        }
+    }
+
+    // Build the scene pipeline for the image that each frame is rendered to, with its wireframe variant if the device supports it.
+    let render_target_format = match app.settings().render_path {
+        RenderPath::DrawImage => instance.draw_image().format(),
+        RenderPath::Swapchain => instance.swapchain().format(),
+    };
+    let scene_pipeline = match scene::ScenePipeline::new(&instance, render_target_format, enabled_features.core.fill_mode_non_solid == vk::TRUE) {
+        Ok(scene_pipeline) => Some(scene_pipeline),
+        Err(e) => {
+            warn!("Failed to create the scene pipeline, so the scene won't be drawn: {e}");
+            None
+        },
+    };
+
+    let fence_timeout = Duration::from_millis(app.settings().fence_timeout_ms).as_nanos() as u64;
+    let staging_pool = vulkan::buffer::StagingPool::new(instance.device(), queue_families.graphics(), constants::STAGING_BUFFER_SIZE, constants::STAGING_POOL_BUDGET, fence_timeout)?;
+    app.client_data_mut().render_data = Some(RenderData {
+        queue_families,
+        selected_physical_device,
+        properties,
+        memory_properties,
+        enabled_features,
+        surface_format: format,
+        present_mode,
+        swapchain_support,
+        draw_image_load_op: vk::AttachmentLoadOp::CLEAR,
+        draw_image_clear_color: None,
+        fence_timeout,
+        interpolation_alpha: 0.0,
+        render_path: app.settings().render_path,
+        swapchain_image_index: 0,
+        frame_timer: metrics::FrameTimer::new(),
+        overlays: Vec::new(),
+        staging_pool,
+        meshes: Vec::new(),
+        scene_pipeline,
+        wireframe: false,
+        paused: false,
+        instance,
+    });
+
+    Ok(())
+}
+
+/// Logs each requested extension and whether it is available.
+fn log_extensions(kind: &str, requested_extensions: &[*const c_char], available_extensions: &[vk::ExtensionProperties]) {
+    debug!("Requested {} {kind} extensions out of {} available:", requested_extensions.len(), available_extensions.len());
+    for requested_extension in requested_extensions {
+        // SAFETY: The extension names are guaranteed to be valid C strings.
+        let requested_extension = unsafe { CStr::from_ptr(*requested_extension) };
+        let available = available_extensions.iter().any(|extension| extension.extension_name_as_c_str() == Ok(requested_extension));
+        debug!("    {} ({})", requested_extension.to_string_lossy(), if available { "available" } else { "unavailable" });
+    }
+}
+
+/// Creates the swapchain and its image views, replacing any existing swapchain, and returns the extent of its images.
+fn create_swapchain(instance: &mut vulkan::Instance, queue_families: &vulkan::queues::QueueFamilies, swapchain_support: &vulkan::swapchain::SwapchainSupport, format: vk::SurfaceFormatKHR, present_mode: vk::PresentModeKHR, window_size: PhysicalSize<u32>) -> RenderResult<vk::Extent2D> {
+    let image_extent = swapchain_support.select_extent(window_size.width, window_size.height);
     let mut swapchain_create_info = vk::SwapchainCreateInfoKHR::default()
         .surface(*instance.surface().deref())
-        .min_image_count(capabilities.min_image_count)
+        .min_image_count(swapchain_support.capabilities().min_image_count)
         .image_format(format.format)
         .image_color_space(format.color_space)
         .image_extent(image_extent)
@@ -111,7 +504,8 @@ pub fn init(app: &mut App, event_loop: &ActiveEventLoop) -> RenderResult<()> {
         .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST);
     let queue_family_indices = vec![queue_families.graphics().queue_info().0, queue_families.present_mode().queue_info().0];
 
-    if queue_families.graphics().queue_info() != queue_families.present_mode().queue_info() {
+    // Only share images between distinct queue families; queues within a family need no ownership transfers.
+    if queue_families.graphics().queue_info().0 != queue_families.present_mode().queue_info().0 {
         swapchain_create_info = swapchain_create_info
             .image_sharing_mode(vk::SharingMode::CONCURRENT)
             .queue_family_indices(queue_family_indices.as_slice());
@@ -120,32 +514,14 @@ pub fn init(app: &mut App, event_loop: &ActiveEventLoop) -> RenderResult<()> {
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE);
     }
 
-    let present_mode = swapchain_support.select_present_mode(vk::PresentModeKHR::MAILBOX);
-    trace!("Present mode: {present_mode:?}");
+    let composite_alpha = swapchain_support.select_composite_alpha();
+    trace!("Composite alpha: {composite_alpha:?}");
     swapchain_create_info = swapchain_create_info
         .pre_transform(swapchain_support.capabilities().current_transform)
-        .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+        .composite_alpha(composite_alpha)
         .present_mode(present_mode);
 
-    // Get queue creation info.
-    let queue_create_infos = queue_families.get_queue_create_infos(&queue_family_map);
-    trace!("Queue Creation Info: {queue_create_infos:?}");
-
-    // Enable special Synchronization2 feature.
-    let mut synchronization2_feature = vk::PhysicalDeviceSynchronization2Features::default()
-        .synchronization2(true);
-    // Create device.
-    let enabled_device_features = &*constants::ENABLED_DEVICE_FEATURES;
-    // don't enable device-specific layers because we don't support shitty Vulkan implementations
-    let device_create_info = vk::DeviceCreateInfo::default()
-        .enabled_features(enabled_device_features)
-        .enabled_extension_names(constants::ENABLED_DEVICE_EXTENSIONS)
-        .queue_create_infos(queue_create_infos.as_slice())
-        .push_next(&mut synchronization2_feature);
-    instance.create_device(selected_physical_device, &device_create_info)?;
-
-    // Create swapchain.
-    instance.create_swapchain(
+    instance.recreate_swapchain(
         &swapchain_create_info,
         |images, format| {
             Vec::from_iter(
@@ -171,51 +547,136 @@ pub fn init(app: &mut App, event_loop: &ActiveEventLoop) -> RenderResult<()> {
         },
     )?;
 
-    // Populate Queue handles.
-    queue_families.populate_handles(instance.device());
-
-    instance.create_framebuffer(
-        vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
-        queue_families.graphics().queue_info().0,
-    )?;
+    Ok(image_extent)
+}
 
+fn create_draw_image(instance: &mut vulkan::Instance, extent: vk::Extent2D) -> RenderResult<()> {
     let draw_image_format = vk::Format::R16G16B16A16_SFLOAT;
-    let draw_image_extent = image_extent;
     let mut draw_image_usages = vk::ImageUsageFlags::empty();
     draw_image_usages |= vk::ImageUsageFlags::TRANSFER_SRC;
     draw_image_usages |= vk::ImageUsageFlags::TRANSFER_DST;
     draw_image_usages |= vk::ImageUsageFlags::STORAGE;
     draw_image_usages |= vk::ImageUsageFlags::COLOR_ATTACHMENT;
-    let draw_image_info = vulkan::util::image_info_2d(draw_image_format, draw_image_extent, draw_image_usages);
+    let draw_image_info = vulkan::util::image_info_2d(draw_image_format, extent, draw_image_usages);
     let draw_image_view_info = vulkan::util::image_view_create_info_2d(draw_image_format, None, vk::ImageAspectFlags::COLOR);
-    instance.create_draw_image(&draw_image_info, &draw_image_view_info, draw_image_extent.into(), draw_image_format)?;
+    instance.create_draw_image(&draw_image_info, &draw_image_view_info, vulkan::util::extent_2d_to_3d(extent), draw_image_format)?;
+    Ok(())
+}
 
-    app.client_data_mut().render_data = Some(RenderData {
-        queue_families,
-        selected_physical_device,
-        instance,
-    });
+/// Scales the swapchain extent by the render scale to get the draw image extent, which is blitted to fit the swapchain.
+/// This has no effect on [`RenderPath::Swapchain`], which renders at the swapchain's size.
+fn scale_extent(extent: vk::Extent2D, render_scale: f32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: ((extent.width as f32 * render_scale).round() as u32).max(1),
+        height: ((extent.height as f32 * render_scale).round() as u32).max(1),
+    }
+}
+
+/// Recreates the swapchain and draw image to match the window's size.
+pub fn resize(app: &mut App) -> RenderResult<()> {
+    let window_size = app.window().inner_size();
+    // Minimized windows have no area to render to, so wait until they're restored.
+    if window_size.width == 0 || window_size.height == 0 {
+        return Ok(())
+    }
+
+    let render_scale = app.settings().render_scale();
+    let render_data = app.render_data_mut();
+    let instance = &mut render_data.instance;
+    instance.device().wait_idle()?;
+
+    render_data.swapchain_support.refresh(instance, render_data.selected_physical_device)?;
+    let image_extent = create_swapchain(instance, &render_data.queue_families, &render_data.swapchain_support, render_data.surface_format, render_data.present_mode, window_size)?;
+    let draw_image_extent = scale_extent(image_extent, render_scale);
+    instance.resize_draw_image(vulkan::util::extent_2d_to_3d(draw_image_extent))?;
+    // Reset the per-frame synchronization primitives, which may still reference the old swapchain's images.
+    instance.framebuffer_mut().flush()?;
+    debug!("Resized swapchain to {}x{}, rendering at {}x{}", image_extent.width, image_extent.height, draw_image_extent.width, draw_image_extent.height);
 
     Ok(())
 }
 
-pub fn begin_render(app: &mut App) -> RenderResult<()> {
-    app.window().request_redraw();
+/// Renders a frame, unless rendering is paused.
+pub fn render(app: &mut App) -> RenderResult<()> {
+    // Skip rendering without requesting another redraw, which pauses the render loop until it is resumed.
+    if app.render_data().is_paused() {
+        return Ok(())
+    }
+    if !begin_render(app)? {
+        return Ok(())
+    }
+    render_background(app)?;
+    render_scene(app)?;
+    render_overlays(app)?;
+    end_render(app)
+}
+
+/// Marks the swapchain to be recreated once resizing has settled (see [`resize`]), e.g. once it no longer matches the surface.
+fn request_resize(app: &mut App) {
+    app.client_data_mut().pending_resize.get_or_insert_with(Instant::now);
+}
+
+/// Begins recording a frame, returning whether it should be rendered.
+/// Frames are skipped while the swapchain is out of date, until it is recreated.
+pub fn begin_render(app: &mut App) -> RenderResult<bool> {
+    // Keep rendering continuously when polling; other control flow strategies request redraws as needed.
+    if app.settings().control_flow == ControlFlowStrategy::Poll {
+        app.window().request_redraw();
+    }
 
+    let safe_mode = app.settings().safe_mode;
     let render_data = app.render_data_mut();
+    if safe_mode {
+        render_data.render_path = RenderPath::DrawImage;
+    }
     let instance = &mut render_data.instance;
     let current_frame = instance.framebuffer().current_frame();
+    render_data.frame_timer.begin_frame();
     // Wait until the GPU has finished rendering the last frame.
-    current_frame.wait_for_render()?;
+    current_frame.wait_for_render(render_data.fence_timeout)?;
+    render_data.frame_timer.fence_waited();
+
+    // Request image from the swapchain.
+    let suboptimal = match instance.swapchain().acquire_next_image(render_data.fence_timeout) {
+        Ok((swapchain_image_index, suboptimal)) => {
+            render_data.swapchain_image_index = swapchain_image_index;
+            suboptimal
+        },
+        // The swapchain no longer matches the surface (e.g. the window is being resized), so skip frames until it is recreated.
+        Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+            request_resize(app);
+            return Ok(false)
+        },
+        Err(e) => return Err(e.into()),
+    };
+    render_data.frame_timer.image_acquired();
 
     // Prepare command buffer.
     let command_buffer_begin_info = vk::CommandBufferBeginInfo::default()
         .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
     current_frame.reset_command_buffer()?;
     current_frame.begin_command_buffer(command_buffer_begin_info)?;
-    current_frame.transition_image(instance.draw_image().image(), vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL)?;
+    match render_data.render_path {
+        RenderPath::DrawImage => {
+            if render_data.draw_image_load_op == vk::AttachmentLoadOp::LOAD {
+                instance.draw_image().transition_to(current_frame, vk::ImageLayout::GENERAL)?;
+            } else {
+                // The draw image's previous contents are discarded, since they're either cleared or don't matter.
+                instance.draw_image().transition(current_frame, vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL)?;
+            }
+        },
+        RenderPath::Swapchain => {
+            let swapchain_image = instance.swapchain().get_image(render_data.swapchain_image_index).expect("image should have been present in swapchain");
+            current_frame.transition_image(swapchain_image, vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)?;
+        },
+    }
 
-    Ok(())
+    // A suboptimal image was still acquired and its semaphore will be signaled, so the frame is rendered and presented
+    // rather than skipped, and the swapchain is recreated afterwards.
+    if suboptimal {
+        request_resize(app);
+    }
+    Ok(true)
 }
 
 pub fn render_background(app: &mut App) -> RenderResult<()> {
@@ -223,43 +684,144 @@ pub fn render_background(app: &mut App) -> RenderResult<()> {
     let instance = &mut render_data.instance;
     let current_frame = instance.framebuffer().current_frame();
 
-    // Draw flashing color.
+    // Swapchain images are undefined upon being acquired, so they're always cleared regardless of the draw image's load op.
+    if render_data.render_path == RenderPath::DrawImage && render_data.draw_image_load_op != vk::AttachmentLoadOp::CLEAR {
+        return Ok(())
+    }
+
+    let _label = current_frame.debug_label_scope(c"Background", [0.2, 0.25, 1.0, 1.0]);
+
+    // Draw flashing color unless a clear color was chosen.
     // \frac{\sin\left(x\right)+1.0}{2}
     let flash = (f32::sin(std::f32::consts::FRAC_PI_2 * instance.framebuffer().current_frame_count() as f32 / (144.0 * 16.0) + 1.0)) / 2.0;
-    let clear_color = vk::ClearColorValue {
+    let clear_color = render_data.draw_image_clear_color.unwrap_or(vk::ClearColorValue {
         float32: [0.2 * flash, 0.25 * flash, flash, 1.0],
+    });
+    match render_data.render_path {
+        RenderPath::DrawImage => {
+            let clear_range = vulkan::util::image_subresource_range(vk::ImageAspectFlags::COLOR);
+            current_frame.cmd_clear_color_image(instance.draw_image().image(), vk::ImageLayout::GENERAL, clear_color, &[clear_range]);
+        },
+        RenderPath::Swapchain => {
+            // Swapchain images can't be cleared as storage images, so clear them by loading them as attachments instead.
+            let swapchain = instance.swapchain();
+            let image_view = swapchain.image_view(render_data.swapchain_image_index).expect("image should have been present in swapchain");
+            let color_attachment = vulkan::util::color_attachment_info(image_view, Some(vk::ClearValue { color: clear_color }), vk::AttachmentLoadOp::CLEAR);
+            current_frame.cmd_begin_rendering(std::slice::from_ref(&color_attachment), None, vk::Rect2D::default().extent(vulkan::util::extent_3d_to_2d(swapchain.extent())));
+            current_frame.cmd_end_rendering();
+        },
+    }
+
+    Ok(())
+}
+
+/// Draws every renderable entity in the world with the scene pipeline, binding each mesh once for all of its instances.
+pub fn render_scene(app: &mut App) -> RenderResult<()> {
+    let draws = scene::collect_draws(app.world());
+    let RenderData { meshes, scene_pipeline, wireframe, instance, render_path, swapchain_image_index, .. } = app.render_data_mut();
+    let Some(scene_pipeline) = scene_pipeline else {
+        return Ok(())
     };
-    let clear_range = vulkan::util::image_subresource_range(vk::ImageAspectFlags::COLOR);
-    current_frame.cmd_clear_color_image(instance.draw_image().image(), vk::ImageLayout::GENERAL, clear_color, &[clear_range]);
+    if draws.is_empty() {
+        return Ok(())
+    }
+    let current_frame = instance.framebuffer().current_frame();
+
+    let _label = current_frame.debug_label_scope(c"Scene", [0.2, 1.0, 0.4, 1.0]);
+
+    let (image_view, extent) = render_target(instance, *render_path, *swapchain_image_index)?;
+    let color_attachment = vulkan::util::color_attachment_info(image_view, None, vk::AttachmentLoadOp::LOAD);
+    current_frame.cmd_begin_rendering(std::slice::from_ref(&color_attachment), None, vk::Rect2D::default().extent(extent));
+    current_frame.cmd_bind_pipeline(vk::PipelineBindPoint::GRAPHICS, scene_pipeline.select(*wireframe));
+    current_frame.cmd_set_viewport_scissor(extent);
+    for draw in draws.iter() {
+        let Some(mesh) = meshes.get(draw.mesh.0) else {
+            warn!("Skipping draws of unknown mesh {:?}", draw.mesh);
+            continue
+        };
+        mesh.bind(current_frame);
+        for transform in draw.transforms.iter() {
+            current_frame.cmd_push_constants(*scene_pipeline.layout, vk::ShaderStageFlags::VERTEX, 0, vulkan::util::as_bytes(&transform.to_cols_array()));
+            current_frame.cmd_draw_indexed(mesh.index_count(), 1, 0, 0, 0);
+        }
+    }
+    current_frame.cmd_end_rendering();
+
+    Ok(())
+}
+
+pub fn render_overlays(app: &mut App) -> RenderResult<()> {
+    let RenderData { overlays, instance, render_path, swapchain_image_index, .. } = app.render_data_mut();
+    if overlays.is_empty() {
+        return Ok(())
+    }
+    let current_frame = instance.framebuffer().current_frame();
+
+    let _label = current_frame.debug_label_scope(c"Overlays", [1.0, 1.0, 1.0, 1.0]);
+
+    let (image_view, extent) = render_target(instance, *render_path, *swapchain_image_index)?;
+    let color_attachment = vulkan::util::color_attachment_info(image_view, None, vk::AttachmentLoadOp::LOAD);
+    current_frame.cmd_begin_rendering(std::slice::from_ref(&color_attachment), None, vk::Rect2D::default().extent(extent));
+    for overlay in overlays.iter_mut() {
+        overlay.record(current_frame, **image_view, extent);
+    }
+    current_frame.cmd_end_rendering();
 
     Ok(())
 }
 
+/// Returns the view and extent of the image that the current frame is rendered to, transitioning it to be rendered to if needed.
+fn render_target(instance: &vulkan::Instance, render_path: RenderPath, swapchain_image_index: u32) -> RenderResult<(&vulkan::ImageView, vk::Extent2D)> {
+    let (image_view, extent) = match render_path {
+        RenderPath::DrawImage => {
+            let draw_image = instance.draw_image();
+            draw_image.transition_to(instance.framebuffer().current_frame(), vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)?;
+            (draw_image.image_view(), draw_image.extent())
+        },
+        RenderPath::Swapchain => {
+            let swapchain = instance.swapchain();
+            (swapchain.image_view(swapchain_image_index).expect("image should have been present in swapchain"), swapchain.extent())
+        },
+    };
+
+    Ok((image_view, vulkan::util::extent_3d_to_2d(extent)))
+}
+
 pub fn end_render(app: &mut App) -> RenderResult<()> {
+    let upscale_filter = app.settings().upscale_filter.into();
     let render_data = app.render_data_mut();
     let instance = &mut render_data.instance;
     let current_frame = instance.framebuffer().current_frame();
 
-    // Request image from the swapchain.
     let swapchain = instance.swapchain();
-    let swapchain_image_index = swapchain.acquire_next_image(current_frame)?;
+    let swapchain_image_index = render_data.swapchain_image_index;
     let swapchain_image = swapchain.get_image(swapchain_image_index).expect("image should have been present in swapchain");
 
-    // Transition draw image back, copy it to the swapchain image, and end command buffer.
-    current_frame.transition_image(instance.draw_image().image(), vk::ImageLayout::GENERAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)?;
-    current_frame.transition_image(swapchain_image, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL)?;
-    let image_subresource_layers = vulkan::util::image_subresource_layers(vk::ImageAspectFlags::COLOR);
-    vulkan::util::memcpy_image(current_frame, instance.draw_image().image(), swapchain_image, instance.draw_image().extent(), swapchain.extent(), image_subresource_layers, image_subresource_layers);
-    current_frame.transition_image(swapchain_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR)?;
+    match render_data.render_path {
+        RenderPath::DrawImage => {
+            // Transition draw image back and copy it to the swapchain image.
+            instance.draw_image().transition_to(current_frame, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)?;
+            current_frame.transition_image(swapchain_image, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL)?;
+            let subresource = vulkan::util::image_subresource_layers(vk::ImageAspectFlags::COLOR);
+            let src = vulkan::util::BlitRegion { image: instance.draw_image().image(), size: instance.draw_image().extent(), subresource };
+            let dst = vulkan::util::BlitRegion { image: swapchain_image, size: swapchain.extent(), subresource };
+            vulkan::util::blit_image(current_frame, &src, &dst, upscale_filter);
+            current_frame.transition_image(swapchain_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR)?;
+        },
+        RenderPath::Swapchain => {
+            current_frame.transition_image(swapchain_image, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR)?;
+        },
+    }
     current_frame.end_command_buffer()?;
 
     // Prepare queue submission.
     let command_buffer_submit_info = vulkan::util::command_buffer_submit_info(current_frame.command_buffer_handle());
-    let wait_semaphore_submit_info = Some(vulkan::util::semaphore_submit_info(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, current_frame.swapchain_semaphore()));
-    let signal_semaphore_submit_info = Some(vulkan::util::semaphore_submit_info(vk::PipelineStageFlags2::ALL_GRAPHICS, current_frame.render_semaphore()));
-    let submit_info = vulkan::util::submit_info(&command_buffer_submit_info, &signal_semaphore_submit_info, &wait_semaphore_submit_info);
+    let wait_semaphore_submit_info = vulkan::util::semaphore_submit_info(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, swapchain.acquire_semaphore(swapchain_image_index));
+    let mut signal_semaphore_submit_infos = vec![vulkan::util::semaphore_submit_info(vk::PipelineStageFlags2::ALL_GRAPHICS, current_frame.render_semaphore())];
+    signal_semaphore_submit_infos.extend(current_frame.timeline_signal_info(instance.framebuffer().timeline_value()));
+    let submit_info = vulkan::util::submit_info_ex(std::slice::from_ref(&command_buffer_submit_info), &signal_semaphore_submit_infos, std::slice::from_ref(&wait_semaphore_submit_info));
     
-    render_data.queue_families.submit_queue(instance.device(), vulkan::queues::QueueType::Graphics, &submit_info, current_frame.render_fence())?;
+    render_data.queue_families.submit_queue(instance.device(), vulkan::queues::QueueType::Graphics, &submit_info, current_frame.submit_fence())?;
 
     let swapchain_handle = swapchain.handle();
     let render_semaphore = current_frame.render_semaphore();
@@ -268,9 +830,19 @@ pub fn end_render(app: &mut App) -> RenderResult<()> {
         .wait_semaphores(std::slice::from_ref(&render_semaphore))
         .image_indices(std::slice::from_ref(&swapchain_image_index));
 
-    swapchain.present_queue(render_data.queue_families.graphics(), &present_info)?;
+    // Present from the present queue, which may belong to a different queue family than the graphics queue.
+    let out_of_date = match swapchain.present_queue(render_data.queue_families.present_mode(), &present_info) {
+        Ok(suboptimal) => suboptimal,
+        // The swapchain no longer matches the surface, so recreate it before rendering to it again.
+        Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => true,
+        Err(e) => return Err(e.into()),
+    };
+    render_data.frame_timer.end_frame();
 
     instance.framebuffer_mut().increment_current_frame();
 
+    if out_of_date {
+        request_resize(app);
+    }
     Ok(())
 }
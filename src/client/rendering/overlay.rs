@@ -0,0 +1,22 @@
+//! # Overlays
+//! An integration point for drawing UI overlays, such as those of egui or imgui backends, on top of the scene.
+//!
+//! Backends typically need the raw Vulkan handles, which are exposed via [`RenderData::instance_handle`], [`RenderData::device_handle`],
+//! [`RenderData::graphics_queue`], [`RenderData::render_target_format`], and [`RenderData::frames_in_flight`].
+//!
+//! [`RenderData::instance_handle`]: super::RenderData::instance_handle
+//! [`RenderData::device_handle`]: super::RenderData::device_handle
+//! [`RenderData::graphics_queue`]: super::RenderData::graphics_queue
+//! [`RenderData::render_target_format`]: super::RenderData::render_target_format
+//! [`RenderData::frames_in_flight`]: super::RenderData::frames_in_flight
+
+use ash::vk;
+
+use super::vulkan::commands::Frame;
+
+pub trait RenderOverlay {
+    /// Records the overlay's draw commands into the frame's command buffer.
+    /// This is called after the scene has been drawn, but before the draw image is copied to the swapchain.
+    /// The commands are recorded within dynamic rendering of `target_view`, which is loaded rather than cleared.
+    fn record(&mut self, frame: &Frame, target_view: vk::ImageView, extent: vk::Extent2D);
+}
@@ -0,0 +1,216 @@
+//! # Scene
+//! The bridge between the ECS and the renderer: components for renderable entities, and the collection of their draws.
+
+use ash::vk;
+use glam::{Mat4, Quat, Vec2, Vec3};
+use hecs::World;
+
+use super::vulkan::{self, buffer::{IndexBuffer, VertexBuffer}, commands::Frame};
+
+/// A handle to a mesh uploaded with [`RenderData::add_mesh`](super::RenderData::add_mesh).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct MeshHandle(pub(super) usize);
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+}
+
+// SAFETY: `Vertex` is `repr(C)` and made up only of `f32`s, so it has no padding.
+unsafe impl vulkan::util::Pod for Vertex {}
+
+/// A mesh in GPU memory.
+pub struct Mesh {
+    vertex_buffer: VertexBuffer<Vertex>,
+    index_buffer: IndexBuffer,
+}
+
+impl Mesh {
+    /// Uploads a mesh to GPU memory via `staging_pool`, without waiting for the upload to finish.
+    /// Commands submitted to `queue` afterwards will see the mesh.
+    pub fn new(device: &vulkan::Device, queue: &vulkan::queues::Queue, staging_pool: &mut vulkan::buffer::StagingPool, vertices: &[Vertex], indices: &[u32]) -> super::RenderResult<Self> {
+        Ok(
+            Self {
+                vertex_buffer: VertexBuffer::new(device, queue, staging_pool, vertices)?,
+                index_buffer: IndexBuffer::new(device, queue, staging_pool, indices)?,
+            }
+        )
+    }
+
+    /// Binds the mesh's vertex and index buffers for drawing.
+    pub fn bind(&self, frame: &Frame) {
+        frame.cmd_bind_vertex_buffers(0, &[**self.vertex_buffer.buffer()], &[0]);
+        frame.cmd_bind_index_buffer(self.index_buffer.buffer(), 0, self.index_buffer.index_type());
+    }
+
+    #[inline]
+    pub fn index_count(&self) -> u32 {
+        self.index_buffer.index_count()
+    }
+}
+
+/// Marks an entity to be drawn with a mesh at its [`Transform`].
+#[derive(Clone, Copy, Debug)]
+pub struct Renderable {
+    pub mesh: MeshHandle,
+}
+
+/// The placement of an entity in the world.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    /// The model matrix, which scales, then rotates, then translates.
+    #[inline]
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+/// Every instance of a mesh to be drawn this frame.
+#[derive(Clone, Debug)]
+pub struct DrawItem {
+    pub mesh: MeshHandle,
+    /// The model matrix of each instance.
+    pub transforms: Vec<Mat4>,
+}
+
+/// Collects the draws of every entity with both a [`Renderable`] and a [`Transform`], batched by mesh so that each mesh is bound once.
+pub fn collect_draws(world: &World) -> Vec<DrawItem> {
+    let mut draws: Vec<DrawItem> = Vec::new();
+    for (_, (renderable, transform)) in world.query::<(&Renderable, &Transform)>().iter() {
+        match draws.binary_search_by_key(&renderable.mesh, |draw| draw.mesh) {
+            Ok(index) => draws[index].transforms.push(transform.matrix()),
+            Err(index) => draws.insert(index, DrawItem { mesh: renderable.mesh, transforms: vec![transform.matrix()] }),
+        }
+    }
+
+    draws
+}
+
+/// The graphics pipeline that renderables are drawn with.
+/// Its layout has a vertex stage push constant range at offset 0 holding the model matrix as a column-major `mat4`,
+/// and its viewport and scissor are dynamic.
+#[derive(Debug)]
+pub struct ScenePipeline {
+    // The pipelines are declared before their layout so that they're destroyed first.
+    pub pipeline: vulkan::Pipeline,
+    /// A variant of the pipeline with [`vk::PolygonMode::LINE`] and the same layout, which is drawn with instead while
+    /// [`RenderData::wireframe`](super::RenderData::wireframe) is set. This requires the `fill_mode_non_solid` feature.
+    pub wireframe: Option<vulkan::Pipeline>,
+    pub layout: vulkan::PipelineLayout,
+}
+
+impl ScenePipeline {
+    /// The shaders that the scene is drawn with, which must have been loaded beforehand.
+    pub const VERTEX_SHADER: &'static str = "scene.vert";
+    pub const FRAGMENT_SHADER: &'static str = "scene.frag";
+
+    /// Creates the scene pipeline for rendering to `color_format` through the instance's pipeline cache,
+    /// along with its wireframe variant if `wireframe` is set (i.e. the `fill_mode_non_solid` feature is enabled).
+    pub fn new(instance: &vulkan::Instance, color_format: vk::Format, wireframe: bool) -> super::RenderResult<Self> {
+        let shader = |name: &str| instance.shader(name).ok_or_else(|| super::RenderError::ShaderNotLoaded(name.to_string()));
+        let stages = [
+            shader(Self::VERTEX_SHADER)?.stage_create_info(vk::ShaderStageFlags::VERTEX, None),
+            shader(Self::FRAGMENT_SHADER)?.stage_create_info(vk::ShaderStageFlags::FRAGMENT, None),
+        ];
+
+        let push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(std::mem::size_of::<Mat4>() as u32);
+        let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+            .push_constant_ranges(std::slice::from_ref(&push_constant_range));
+        let layout = instance.device().create_pipeline_layout(&layout_create_info)?;
+
+        let create_pipeline = |polygon_mode: vk::PolygonMode| {
+            let vertex_binding = vk::VertexInputBindingDescription::default()
+                .binding(0)
+                .stride(std::mem::size_of::<Vertex>() as u32)
+                .input_rate(vk::VertexInputRate::VERTEX);
+            let vertex_attributes = [
+                vk::VertexInputAttributeDescription::default()
+                    .location(0)
+                    .binding(0)
+                    .format(vk::Format::R32G32B32_SFLOAT)
+                    .offset(std::mem::offset_of!(Vertex, position) as u32),
+                vk::VertexInputAttributeDescription::default()
+                    .location(1)
+                    .binding(0)
+                    .format(vk::Format::R32G32B32_SFLOAT)
+                    .offset(std::mem::offset_of!(Vertex, normal) as u32),
+            ];
+            let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default()
+                .vertex_binding_descriptions(std::slice::from_ref(&vertex_binding))
+                .vertex_attribute_descriptions(&vertex_attributes);
+            let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::default()
+                .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+            let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+                .viewport_count(1)
+                .scissor_count(1);
+            let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+                .polygon_mode(polygon_mode)
+                .cull_mode(vk::CullModeFlags::NONE)
+                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+                .line_width(1.0);
+            let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+                .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+            let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
+                .color_write_mask(vk::ColorComponentFlags::RGBA);
+            let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+                .attachments(std::slice::from_ref(&color_blend_attachment));
+            let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+            let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+                .dynamic_states(&dynamic_states);
+            // The scene is drawn with dynamic rendering, so the pipeline declares its attachment formats instead of a render pass.
+            let mut rendering_create_info = vk::PipelineRenderingCreateInfo::default()
+                .color_attachment_formats(std::slice::from_ref(&color_format));
+            let create_info = vk::GraphicsPipelineCreateInfo::default()
+                .stages(&stages)
+                .vertex_input_state(&vertex_input_state)
+                .input_assembly_state(&input_assembly_state)
+                .viewport_state(&viewport_state)
+                .rasterization_state(&rasterization_state)
+                .multisample_state(&multisample_state)
+                .color_blend_state(&color_blend_state)
+                .dynamic_state(&dynamic_state)
+                .layout(*layout)
+                .push_next(&mut rendering_create_info);
+            instance.device().create_graphics_pipeline(instance.pipeline_cache(), &create_info)
+        };
+
+        Ok(
+            Self {
+                pipeline: create_pipeline(vk::PolygonMode::FILL)?,
+                wireframe: if wireframe { Some(create_pipeline(vk::PolygonMode::LINE)?) } else { None },
+                layout,
+            }
+        )
+    }
+
+    /// The pipeline to draw with, which is the wireframe variant if `wireframe` is set and it exists.
+    #[inline]
+    pub fn select(&self, wireframe: bool) -> vk::Pipeline {
+        match &self.wireframe {
+            Some(wireframe_pipeline) if wireframe => **wireframe_pipeline,
+            _ => *self.pipeline,
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! # Allocated Buffers
+//! Buffers allocated in GPU memory, such as vertex and index buffers.
+
+use std::{marker::PhantomData, rc::Rc};
+
+use ash::{prelude::VkResult, vk};
+
+use super::util::{as_bytes, Pod};
+use crate::client::rendering::RenderResult;
+
+pub struct AllocatedBuffer {
+    buffer: super::Buffer,
+    size: vk::DeviceSize,
+}
+
+impl AllocatedBuffer {
+    pub(super) fn new(device: &super::Device, size: vk::DeviceSize, usage: vk::BufferUsageFlags, allocation_create_info: &vk_mem::AllocationCreateInfo) -> RenderResult<Self> {
+        let create_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        Ok(
+            Self {
+                buffer: device.create_buffer(&create_info, allocation_create_info)?,
+                size,
+            }
+        )
+    }
+
+    /// Creates a host-visible buffer containing `data` to be copied elsewhere.
+    pub(super) fn new_staging(device: &super::Device, data: &[u8]) -> RenderResult<Self> {
+        let allocation_create_info = vk_mem::AllocationCreateInfo {
+            usage: vk_mem::MemoryUsage::Auto,
+            flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let mut staging_buffer = Self::new(device, data.len() as vk::DeviceSize, vk::BufferUsageFlags::TRANSFER_SRC, &allocation_create_info)?;
+        staging_buffer.write(data)?;
+        Ok(staging_buffer)
+    }
+
+    /// Creates a device-local buffer and uploads `data` to it via a staging buffer.
+    /// This blocks until the upload has finished.
+    pub(super) fn new_device_local(device: &super::Device, queue: &super::queues::Queue, data: &[u8], usage: vk::BufferUsageFlags) -> RenderResult<Self> {
+        let size = data.len() as vk::DeviceSize;
+        let staging_buffer = Self::new_staging(device, data)?;
+        let allocation_create_info = vk_mem::AllocationCreateInfo {
+            usage: vk_mem::MemoryUsage::AutoPreferDevice,
+            required_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ..Default::default()
+        };
+        let buffer = Self::new(device, size, usage | vk::BufferUsageFlags::TRANSFER_DST, &allocation_create_info)?;
+        device.immediate_submit(queue, |command_buffer| {
+            let region = vk::BufferCopy::default()
+                .size(size);
+            // SAFETY: The device is available at this point.
+            unsafe { device.inner.cmd_copy_buffer(command_buffer, *staging_buffer.buffer, *buffer.buffer, std::slice::from_ref(&region)); }
+        })?;
+        Ok(buffer)
+    }
+
+    /// Copies `data` to the start of the buffer, which must be host-visible.
+    pub fn write(&mut self, data: &[u8]) -> VkResult<()> {
+        assert!(data.len() as vk::DeviceSize <= self.size, "data must fit within the buffer");
+        let (allocator, allocation) = self.buffer.1.as_mut().expect("allocated buffers always have an allocation");
+        // SAFETY: The allocation is host-visible, and the mapped memory is at least as large as the data.
+        unsafe {
+            let mapped_data = allocator.map_memory(allocation)?;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), mapped_data, data.len());
+            allocator.unmap_memory(allocation);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn buffer(&self) -> &super::Buffer {
+        &self.buffer
+    }
+
+    #[inline]
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+}
+
+/// A ring of host-visible staging buffers that are reused across uploads rather than allocated for each one.
+/// Each buffer is recycled once the fence of its last upload signals, so uploads don't block unless the whole ring is in flight.
+pub struct StagingPool {
+    slots: Vec<StagingSlot>,
+    buffer_size: vk::DeviceSize,
+    next_slot: usize,
+    /// The timeout of waits on uploads before they're retried, in nanoseconds.
+    fence_timeout: u64,
+    device: ash::Device,
+}
+
+struct StagingSlot {
+    buffer: AllocatedBuffer,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    fence: super::Fence,
+    /// The destination of the upload in flight, which is kept alive until the fence signals.
+    destination: Option<Rc<AllocatedBuffer>>,
+}
+
+impl StagingPool {
+    /// Creates a pool of `budget / buffer_size` staging buffers (at least one) of `buffer_size` bytes each.
+    /// Waits on uploads are retried if they take longer than `fence_timeout` nanoseconds.
+    pub fn new(device: &super::Device, queue: &super::queues::Queue, buffer_size: vk::DeviceSize, budget: vk::DeviceSize, fence_timeout: u64) -> RenderResult<Self> {
+        let allocation_create_info = vk_mem::AllocationCreateInfo {
+            usage: vk_mem::MemoryUsage::Auto,
+            flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let command_pool_create_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(queue.queue_info().0);
+        let mut pool = Self {
+            slots: Vec::new(),
+            buffer_size,
+            next_slot: 0,
+            fence_timeout,
+            device: device.inner.clone(),
+        };
+        for _ in 0..(budget / buffer_size).max(1) {
+            // SAFETY: The command pool is destroyed upon dropping the pool, including if a later slot fails to be created.
+            let command_pool = unsafe { device.inner.create_command_pool(&command_pool_create_info, None)? };
+            let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::default()
+                .command_pool(command_pool)
+                .command_buffer_count(1)
+                .level(vk::CommandBufferLevel::PRIMARY);
+            // SAFETY: The buffer is automatically destroyed upon its command pool being destroyed.
+            let command_buffer = match unsafe { device.inner.allocate_command_buffers(&command_buffer_allocate_info) } {
+                Ok(command_buffers) => command_buffers[0],
+                Err(e) => {
+                    // SAFETY: The command pool isn't in use yet.
+                    unsafe { device.inner.destroy_command_pool(command_pool, None); }
+                    return Err(e.into())
+                },
+            };
+            let (buffer, fence) = match (AllocatedBuffer::new(device, buffer_size, vk::BufferUsageFlags::TRANSFER_SRC, &allocation_create_info), device.create_fence(false)) {
+                (Ok(buffer), Ok(fence)) => (buffer, fence),
+                (Err(e), _) => {
+                    // SAFETY: The command pool isn't in use yet.
+                    unsafe { device.inner.destroy_command_pool(command_pool, None); }
+                    return Err(e)
+                },
+                (_, Err(e)) => {
+                    // SAFETY: The command pool isn't in use yet.
+                    unsafe { device.inner.destroy_command_pool(command_pool, None); }
+                    return Err(e.into())
+                },
+            };
+            pool.slots.push(StagingSlot { buffer, command_pool, command_buffer, fence, destination: None });
+        }
+
+        Ok(pool)
+    }
+
+    /// Creates a device-local buffer and uploads `data` to it via the next staging buffer in the ring, without waiting for the upload to finish.
+    /// Commands submitted to the same queue afterwards will see the uploaded data.
+    /// Data larger than the pool's buffers is uploaded via a one-off staging buffer instead, which blocks until the upload has finished.
+    ///
+    /// The pool shares ownership of the returned buffer until the upload has finished, so it may be dropped at any time.
+    pub fn upload(&mut self, device: &super::Device, queue: &super::queues::Queue, data: &[u8], usage: vk::BufferUsageFlags) -> RenderResult<Rc<AllocatedBuffer>> {
+        let size = data.len() as vk::DeviceSize;
+        if size > self.buffer_size {
+            return Ok(Rc::new(AllocatedBuffer::new_device_local(device, queue, data, usage)?))
+        }
+
+        let slot_index = self.next_slot;
+        self.next_slot = (slot_index + 1) % self.slots.len();
+        let slot = &mut self.slots[slot_index];
+        // The ring has wrapped around to a buffer that may still be in use, so wait for its upload to finish.
+        if slot.destination.is_some() {
+            super::util::retry_on_timeout("a staging upload to finish", || device.wait_for_fences(&[*slot.fence], true, self.fence_timeout))?;
+            device.reset_fences(&[*slot.fence])?;
+            slot.destination = None;
+        }
+        slot.buffer.write(data)?;
+
+        let allocation_create_info = vk_mem::AllocationCreateInfo {
+            usage: vk_mem::MemoryUsage::AutoPreferDevice,
+            required_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ..Default::default()
+        };
+        let buffer = AllocatedBuffer::new(device, size, usage | vk::BufferUsageFlags::TRANSFER_DST, &allocation_create_info)?;
+        // SAFETY: The command buffer isn't in use, since its fence has signaled.
+        unsafe {
+            device.inner.reset_command_pool(slot.command_pool, vk::CommandPoolResetFlags::empty())?;
+            let begin_info = vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            device.inner.begin_command_buffer(slot.command_buffer, &begin_info)?;
+            let region = vk::BufferCopy::default()
+                .size(size);
+            device.inner.cmd_copy_buffer(slot.command_buffer, *slot.buffer.buffer, *buffer.buffer, std::slice::from_ref(&region));
+            // Make the copy visible to everything submitted to the queue afterwards.
+            let memory_barrier = vk::MemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ);
+            let dependency_info = vk::DependencyInfo::default()
+                .memory_barriers(std::slice::from_ref(&memory_barrier));
+            device.inner.cmd_pipeline_barrier2(slot.command_buffer, &dependency_info);
+            device.inner.end_command_buffer(slot.command_buffer)?;
+        }
+        let command_buffer_submit_info = super::util::command_buffer_submit_info(slot.command_buffer);
+        let submit_info = super::util::submit_info(&command_buffer_submit_info, &None, &None);
+        device.submit_queue(queue.handle(), &submit_info, *slot.fence)?;
+        let buffer = Rc::new(buffer);
+        slot.destination = Some(buffer.clone());
+
+        Ok(buffer)
+    }
+
+    /// Blocks until every upload has finished.
+    pub fn wait_idle(&mut self) -> VkResult<()> {
+        for slot in self.slots.iter_mut().filter(|slot| slot.destination.is_some()) {
+            // SAFETY: The device is available at this point.
+            unsafe {
+                super::util::retry_on_timeout("a staging upload to finish", || self.device.wait_for_fences(&[*slot.fence], true, self.fence_timeout))?;
+                self.device.reset_fences(&[*slot.fence])?;
+            }
+            slot.destination = None;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn buffer_size(&self) -> vk::DeviceSize {
+        self.buffer_size
+    }
+}
+
+impl Drop for StagingPool {
+    fn drop(&mut self) {
+        if let Err(e) = self.wait_idle() {
+            crate::error!("Failed to wait for staging uploads to finish: {e}");
+        }
+        for slot in self.slots.iter() {
+            // SAFETY: No uploads are in flight anymore.
+            unsafe { self.device.destroy_command_pool(slot.command_pool, None); }
+        }
+    }
+}
+
+/// A device-local buffer of vertices.
+pub struct VertexBuffer<T: Pod> {
+    buffer: Rc<AllocatedBuffer>,
+    vertex_count: u32,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> VertexBuffer<T> {
+    /// Uploads `vertices` via `staging_pool` (see [`StagingPool::upload`]).
+    pub fn new(device: &super::Device, queue: &super::queues::Queue, staging_pool: &mut StagingPool, vertices: &[T]) -> RenderResult<Self> {
+        Ok(
+            Self {
+                buffer: staging_pool.upload(device, queue, as_bytes(vertices), vk::BufferUsageFlags::VERTEX_BUFFER)?,
+                vertex_count: vertices.len() as u32,
+                _marker: PhantomData,
+            }
+        )
+    }
+
+    #[inline]
+    pub fn buffer(&self) -> &super::Buffer {
+        self.buffer.buffer()
+    }
+
+    #[inline]
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+}
+
+/// A device-local buffer of 32-bit indices.
+pub struct IndexBuffer {
+    buffer: Rc<AllocatedBuffer>,
+    index_count: u32,
+}
+
+impl IndexBuffer {
+    /// Uploads `indices` via `staging_pool` (see [`StagingPool::upload`]).
+    pub fn new(device: &super::Device, queue: &super::queues::Queue, staging_pool: &mut StagingPool, indices: &[u32]) -> RenderResult<Self> {
+        Ok(
+            Self {
+                buffer: staging_pool.upload(device, queue, as_bytes(indices), vk::BufferUsageFlags::INDEX_BUFFER)?,
+                index_count: indices.len() as u32,
+            }
+        )
+    }
+
+    #[inline]
+    pub fn buffer(&self) -> &super::Buffer {
+        self.buffer.buffer()
+    }
+
+    #[inline]
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    #[inline]
+    pub fn index_type(&self) -> vk::IndexType {
+        vk::IndexType::UINT32
+    }
+}
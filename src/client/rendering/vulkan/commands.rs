@@ -1,41 +1,65 @@
 //! # Vulkan Commands
 //! An abstraction for queueing and executing Vulkan commands.
 
-use std::mem::MaybeUninit;
+use std::{cell::Cell, ffi::CStr, fmt, mem::MaybeUninit};
 
-use ash::{prelude::VkResult, vk};
+use ash::{ext, prelude::VkResult, vk};
 
 use crate::constants;
 
+/// How the CPU waits for a frame to finish rendering before reusing its resources.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum FrameSync {
+    /// Each frame waits on its own fence.
+    #[default]
+    Binary,
+    /// Frames wait on a single timeline semaphore shared by the framebuffer, which counts completed submissions.
+    /// This requires the `timelineSemaphore` feature.
+    Timeline,
+}
+
 /// A collection of a frame's Vulkan commands.
 pub struct Frame {
     command_pool_handle: vk::CommandPool,
     command_buffer_handle: vk::CommandBuffer,
-    swapchain_semaphore: vk::Semaphore,
+    /// The info the command pool was created with, which is reused upon recreating it.
+    command_pool_flags: vk::CommandPoolCreateFlags,
+    queue_family_index: super::QueueFamilyIndex,
     render_semaphore: vk::Semaphore,
     render_fence: vk::Fence,
+    /// The framebuffer's timeline semaphore, which the framebuffer destroys, if using [`FrameSync::Timeline`].
+    timeline_semaphore: Option<vk::Semaphore>,
+    /// The timeline value signaled upon the frame's last submission finishing.
+    timeline_value: Cell<u64>,
+    /// Whether the primary command buffer is between [`Frame::begin_command_buffer`] and [`Frame::end_command_buffer`].
+    recording: Cell<bool>,
+    /// Whether the frame's last submission may still be executing, i.e. it hasn't been waited on since being submitted.
+    in_flight: Cell<bool>,
     device: ash::Device,
+    debug_utils: Option<ext::debug_utils::Device>,
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Frame")
+            .field("command_pool_handle", &self.command_pool_handle)
+            .field("command_buffer_handle", &self.command_buffer_handle)
+            .field("render_semaphore", &self.render_semaphore)
+            .field("render_fence", &self.render_fence)
+            .field("timeline_semaphore", &self.timeline_semaphore)
+            .field("timeline_value", &self.timeline_value.get())
+            .field("recording", &self.recording.get())
+            .field("in_flight", &self.in_flight.get())
+            .finish_non_exhaustive()
+    }
 }
 
 impl Frame {
-    pub(super) fn new(device: ash::Device, command_pool_flags: vk::CommandPoolCreateFlags, queue_family_index: super::QueueFamilyIndex) -> VkResult<Self> {
-        let command_pool_create_info = vk::CommandPoolCreateInfo::default()
-            .flags(command_pool_flags)
-            .queue_family_index(queue_family_index);
-        // SAFETY: The object is automatically destroyed.
-        let command_pool_handle = unsafe { device.create_command_pool(&command_pool_create_info, None)? };
-        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::default()
-            .command_pool(command_pool_handle.clone())
-            .command_buffer_count(1)
-            .level(vk::CommandBufferLevel::PRIMARY);
-        // SAFETY: The buffer is automatically destroyed upon its command pool being destroyed.
-        let command_buffer_handles = unsafe { device.allocate_command_buffers(&command_buffer_allocate_info)? };
-        let command_buffer_handle = command_buffer_handles[0];
+    pub(super) fn new(device: ash::Device, debug_utils: Option<ext::debug_utils::Device>, command_pool_flags: vk::CommandPoolCreateFlags, queue_family_index: super::QueueFamilyIndex, timeline_semaphore: Option<vk::Semaphore>) -> VkResult<Self> {
+        let (command_pool_handle, command_buffer_handle) = Self::create_command_pool(&device, command_pool_flags, queue_family_index)?;
         let semaphore_create_info = vk::SemaphoreCreateInfo::default()
             .flags(vk::SemaphoreCreateFlags::empty());
         // SAFETY: The object is automatically destroyed.
-        let swapchain_semaphore = unsafe { device.create_semaphore(&semaphore_create_info, None)? };
-        // SAFETY: The object is automatically destroyed.
         let render_semaphore = unsafe { device.create_semaphore(&semaphore_create_info, None)? };
         let fence_create_info = vk::FenceCreateInfo::default()
             .flags(vk::FenceCreateFlags::SIGNALED);
@@ -45,35 +69,172 @@ impl Frame {
             Self {
                 command_pool_handle,
                 command_buffer_handle,
-                swapchain_semaphore,
+                command_pool_flags,
+                queue_family_index,
                 render_semaphore,
                 render_fence,
+                timeline_semaphore,
+                timeline_value: Cell::new(0),
+                recording: Cell::new(false),
+                in_flight: Cell::new(false),
                 device,
+                debug_utils,
             }
         )
     }
 
+    /// Creates a command pool and allocates the frame's primary command buffer from it.
+    fn create_command_pool(device: &ash::Device, command_pool_flags: vk::CommandPoolCreateFlags, queue_family_index: super::QueueFamilyIndex) -> VkResult<(vk::CommandPool, vk::CommandBuffer)> {
+        let command_pool_create_info = vk::CommandPoolCreateInfo::default()
+            .flags(command_pool_flags)
+            .queue_family_index(queue_family_index);
+        // SAFETY: The object is automatically destroyed.
+        let command_pool_handle = unsafe { device.create_command_pool(&command_pool_create_info, None)? };
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(command_pool_handle)
+            .command_buffer_count(1)
+            .level(vk::CommandBufferLevel::PRIMARY);
+        // SAFETY: The buffer is automatically destroyed upon its command pool being destroyed.
+        match unsafe { device.allocate_command_buffers(&command_buffer_allocate_info) } {
+            Ok(command_buffer_handles) => Ok((command_pool_handle, command_buffer_handles[0])),
+            Err(e) => {
+                // SAFETY: The pool was just created, so nothing is using it.
+                unsafe { device.destroy_command_pool(command_pool_handle, None); }
+                Err(e)
+            },
+        }
+    }
+
     #[inline]
     pub fn command_buffer_handle(&self) -> vk::CommandBuffer {
         self.command_buffer_handle
     }
 
+    /// Resets every command buffer allocated from the frame's command pool, returning their memory to the pool.
+    /// The GPU must have finished executing them, e.g. after [`Self::wait_for_render`].
+    #[inline]
+    pub fn reset_pool(&self) -> VkResult<()> {
+        debug_assert!(!self.recording.get(), "cannot reset a command pool while its primary command buffer is recording");
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.reset_command_pool(self.command_pool_handle, vk::CommandPoolResetFlags::RELEASE_RESOURCES) }
+    }
+
+    /// Replaces the command pool with a new one, freeing every command buffer allocated from it, and allocates a new primary command buffer.
+    ///
+    /// # Safety
+    /// The GPU must have finished executing the frame's command buffers, and the primary command buffer must not be recording.
+    pub unsafe fn recreate_pool(&mut self) -> VkResult<()> {
+        debug_assert!(!self.recording.get(), "cannot recreate a command pool while its primary command buffer is recording");
+        let (command_pool_handle, command_buffer_handle) = Self::create_command_pool(&self.device, self.command_pool_flags, self.queue_family_index)?;
+        // SAFETY: The GPU is no longer using the old pool's command buffers.
+        unsafe { self.device.destroy_command_pool(self.command_pool_handle, None); }
+        self.command_pool_handle = command_pool_handle;
+        self.command_buffer_handle = command_buffer_handle;
+        Ok(())
+    }
+
+    /// Allocates additional command buffers from the frame's command pool, which are freed along with it.
+    /// If the pool has run out of memory, it is recreated and the allocation retried once, which frees every command buffer
+    /// previously allocated from it (including the primary command buffer, which is reallocated). The frame's last submission is waited on first,
+    /// retrying if it takes longer than `timeout` nanoseconds, and [`vk::Result::ERROR_OUT_OF_POOL_MEMORY`] is returned if the primary command buffer is recording.
+    pub fn allocate_command_buffers(&mut self, level: vk::CommandBufferLevel, count: u32, timeout: u64) -> VkResult<Vec<vk::CommandBuffer>> {
+        let allocate = |device: &ash::Device, command_pool_handle: vk::CommandPool| {
+            let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::default()
+                .command_pool(command_pool_handle)
+                .command_buffer_count(count)
+                .level(level);
+            // SAFETY: The buffers are automatically destroyed upon their command pool being destroyed.
+            unsafe { device.allocate_command_buffers(&command_buffer_allocate_info) }
+        };
+        match allocate(&self.device, self.command_pool_handle) {
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) if self.recording.get() => {
+                crate::error!("Command pool ran out of memory while its primary command buffer is recording, so it can't be recreated");
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+            },
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) => {
+                crate::warn!("Command pool ran out of memory, so recreating it");
+                self.wait_for_idle(timeout)?;
+                // SAFETY: The frame's last submission has finished and the primary command buffer isn't recording.
+                unsafe { self.recreate_pool()?; }
+                allocate(&self.device, self.command_pool_handle)
+            },
+            result => result,
+        }
+    }
+
     // Command Buffer Management
 
-    /// Wait for rendering to finish.
+    /// Waits for the frame's last submission to finish without resetting its fence, retrying if it takes longer than `timeout` nanoseconds.
+    /// Nothing is waited on if the frame hasn't been submitted since it was last waited on, as its fence would never be signaled.
+    fn wait_for_idle(&self, timeout: u64) -> VkResult<()> {
+        if !self.in_flight.get() {
+            return Ok(())
+        }
+        if let Some(timeline_semaphore) = self.timeline_semaphore {
+            let timeline_value = self.timeline_value.get();
+            let wait_info = vk::SemaphoreWaitInfo::default()
+                .semaphores(std::slice::from_ref(&timeline_semaphore))
+                .values(std::slice::from_ref(&timeline_value));
+            // SAFETY: The device is available at this point.
+            super::util::retry_on_timeout("the frame to finish executing", || unsafe { self.device.wait_semaphores(&wait_info, timeout) })?;
+        } else {
+            // SAFETY: The device is available at this point.
+            super::util::retry_on_timeout("the frame to finish executing", || unsafe { self.device.wait_for_fences(&[self.render_fence], true, timeout) })?;
+        }
+        self.in_flight.set(false);
+        Ok(())
+    }
+
+    /// Wait for rendering to finish, retrying if it takes longer than `timeout` nanoseconds.
+    /// Nothing is waited on if the frame hasn't been submitted since it was last waited on (e.g. because it was skipped),
+    /// since its fence has already been reset and would never be signaled.
     #[inline]
-    pub fn wait_for_render(&self) -> VkResult<()> {
+    pub fn wait_for_render(&self, timeout: u64) -> VkResult<()> {
+        if !self.in_flight.get() {
+            // The fence is signaled upon creation, so make sure it's unsignaled for the next submission.
+            if self.timeline_semaphore.is_none() {
+                // SAFETY: The device is available at this point.
+                unsafe { self.device.reset_fences(&[self.render_fence])?; }
+            }
+            return Ok(())
+        }
+        if let Some(timeline_semaphore) = self.timeline_semaphore {
+            let timeline_value = self.timeline_value.get();
+            let wait_info = vk::SemaphoreWaitInfo::default()
+                .semaphores(std::slice::from_ref(&timeline_semaphore))
+                .values(std::slice::from_ref(&timeline_value));
+            // SAFETY: The device is available at this point.
+            super::util::retry_on_timeout("the previous frame to render", || unsafe { self.device.wait_semaphores(&wait_info, timeout) })?;
+            self.in_flight.set(false);
+            return Ok(())
+        }
+
         // SAFETY: The device is available at this point.
         unsafe {
-            self.device.wait_for_fences(&[self.render_fence], true, constants::FENCE_TIMEOUT)?;
+            super::util::retry_on_timeout("the previous frame to render", || self.device.wait_for_fences(&[self.render_fence], true, timeout))?;
             self.device.reset_fences(&[self.render_fence])?;
         }
+        self.in_flight.set(false);
         Ok(())
     }
 
+    /// Creates the info for signaling the timeline semaphore with `value` upon the frame's submission finishing, if using [`FrameSync::Timeline`].
+    /// The value must exceed every value signaled before it, and is waited on by the next [`Self::wait_for_render`].
+    pub fn timeline_signal_info<'a>(&self, value: u64) -> Option<vk::SemaphoreSubmitInfo<'a>> {
+        let timeline_semaphore = self.timeline_semaphore?;
+        self.timeline_value.set(value);
+        Some(super::util::semaphore_submit_info_ex(vk::PipelineStageFlags2::ALL_COMMANDS, timeline_semaphore, 0, value))
+    }
+
+    /// The fence to signal upon the frame's submission finishing, which is null if using [`FrameSync::Timeline`].
+    /// This marks the frame as in flight until it is next waited on.
     #[inline]
-    pub fn swapchain_semaphore(&self) -> vk::Semaphore {
-        self.swapchain_semaphore
+    pub fn submit_fence(&self) -> vk::Fence {
+        self.in_flight.set(true);
+        match self.timeline_semaphore {
+            Some(_) => vk::Fence::null(),
+            None => self.render_fence,
+        }
     }
 
     #[inline]
@@ -88,6 +249,7 @@ impl Frame {
 
     #[inline]
     pub fn reset_command_buffer(&self) -> VkResult<()> {
+        self.recording.set(false);
         // SAFETY: The device is available at this point.
         unsafe { self.device.reset_command_buffer(self.command_buffer_handle, vk::CommandBufferResetFlags::empty()) }
     }
@@ -95,11 +257,14 @@ impl Frame {
     #[inline]
     pub fn begin_command_buffer(&self, begin_info: vk::CommandBufferBeginInfo) -> VkResult<()> {
         // SAFETY: The device is available at this point.
-        unsafe { self.device.begin_command_buffer(self.command_buffer_handle, &begin_info) }
+        unsafe { self.device.begin_command_buffer(self.command_buffer_handle, &begin_info)?; }
+        self.recording.set(true);
+        Ok(())
     }
 
     #[inline]
     pub fn end_command_buffer(&self) -> VkResult<()> {
+        self.recording.set(false);
         // SAFETY: The device is available at this point.
         unsafe { self.device.end_command_buffer(self.command_buffer_handle) }
     }
@@ -112,14 +277,166 @@ impl Frame {
         unsafe { self.device.cmd_clear_color_image(self.command_buffer_handle, **image, image_layout, &clear_color_value, ranges); }
     }
 
+    /// Clears the depth and/or stencil aspects of `image`, which must be in [`vk::ImageLayout::TRANSFER_DST_OPTIMAL`] or [`vk::ImageLayout::GENERAL`].
+    /// Use [`super::util::image_subresource_range`] with [`vk::ImageAspectFlags::DEPTH`] to clear the whole depth image.
+    #[inline]
+    pub fn cmd_clear_depth_stencil_image(&self, image: &super::Image, image_layout: vk::ImageLayout, depth: f32, stencil: u32, ranges: &[vk::ImageSubresourceRange]) {
+        debug_assert!(
+            image_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL || image_layout == vk::ImageLayout::GENERAL,
+            "depth/stencil images must be cleared in the TRANSFER_DST_OPTIMAL or GENERAL layout, not {image_layout:?}",
+        );
+        let clear_value = vk::ClearDepthStencilValue { depth, stencil };
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_clear_depth_stencil_image(self.command_buffer_handle, **image, image_layout, &clear_value, ranges); }
+    }
+
     #[inline]
     pub fn cmd_blit_image_2(&self, blit_info: &vk::BlitImageInfo2) {
         // SAFETY: The device is available at this point.
         unsafe { self.device.cmd_blit_image2(self.command_buffer_handle, blit_info) }
     }
 
+    /// Begins dynamic rendering into the given attachments, without a render pass.
+    #[inline]
+    pub fn cmd_begin_rendering(&self, color_attachments: &[vk::RenderingAttachmentInfo], depth_attachment: Option<&vk::RenderingAttachmentInfo>, render_area: vk::Rect2D) {
+        let mut rendering_info = vk::RenderingInfo::default()
+            .render_area(render_area)
+            .layer_count(1)
+            .color_attachments(color_attachments);
+        if let Some(depth_attachment) = depth_attachment {
+            rendering_info = rendering_info.depth_attachment(depth_attachment);
+        }
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_begin_rendering(self.command_buffer_handle, &rendering_info); }
+    }
+
+    #[inline]
+    pub fn cmd_end_rendering(&self) {
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_end_rendering(self.command_buffer_handle); }
+    }
+
+    /// Copies an image into a buffer, e.g. for screenshots or reading back compute results.
+    /// The image must be in the [`vk::ImageLayout::TRANSFER_SRC_OPTIMAL`] (or [`vk::ImageLayout::GENERAL`]) layout,
+    /// and the buffer must have been created with [`vk::BufferUsageFlags::TRANSFER_DST`].
+    #[inline]
+    pub fn cmd_copy_image_to_buffer(&self, image: &super::Image, image_layout: vk::ImageLayout, buffer: &super::Buffer, extent: vk::Extent3D, subresource: vk::ImageSubresourceLayers) {
+        // A buffer row length and image height of 0 means that the buffer is tightly packed.
+        let region = vk::BufferImageCopy2::default()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(subresource)
+            .image_offset(vk::Offset3D::default())
+            .image_extent(extent);
+        let copy_info = vk::CopyImageToBufferInfo2::default()
+            .src_image(**image)
+            .src_image_layout(image_layout)
+            .dst_buffer(**buffer)
+            .regions(std::slice::from_ref(&region));
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_copy_image_to_buffer2(self.command_buffer_handle, &copy_info); }
+    }
+
+    #[inline]
+    pub fn cmd_bind_pipeline(&self, pipeline_bind_point: vk::PipelineBindPoint, pipeline: vk::Pipeline) {
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_bind_pipeline(self.command_buffer_handle, pipeline_bind_point, pipeline); }
+    }
+
+    #[inline]
+    pub fn cmd_push_constants(&self, layout: vk::PipelineLayout, stage_flags: vk::ShaderStageFlags, offset: u32, constants: &[u8]) {
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_push_constants(self.command_buffer_handle, layout, stage_flags, offset, constants); }
+    }
+
+    #[inline]
+    pub fn cmd_bind_vertex_buffers(&self, first_binding: u32, buffers: &[vk::Buffer], offsets: &[vk::DeviceSize]) {
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_bind_vertex_buffers(self.command_buffer_handle, first_binding, buffers, offsets); }
+    }
+
+    #[inline]
+    pub fn cmd_bind_index_buffer(&self, buffer: &super::Buffer, offset: vk::DeviceSize, index_type: vk::IndexType) {
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_bind_index_buffer(self.command_buffer_handle, **buffer, offset, index_type); }
+    }
+
+    #[inline]
+    pub fn cmd_draw_indexed(&self, index_count: u32, instance_count: u32, first_index: u32, vertex_offset: i32, first_instance: u32) {
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_draw_indexed(self.command_buffer_handle, index_count, instance_count, first_index, vertex_offset, first_instance); }
+    }
+
+    /// Sets the dynamic viewport to cover the whole extent with a depth range of 0 to 1.
+    #[inline]
+    pub fn cmd_set_viewport(&self, extent: vk::Extent2D) {
+        let viewport = vk::Viewport::default()
+            .x(0.0)
+            .y(0.0)
+            .width(extent.width as f32)
+            .height(extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_set_viewport(self.command_buffer_handle, 0, std::slice::from_ref(&viewport)); }
+    }
+
+    /// Sets the dynamic scissor to cover the whole extent from the origin.
+    #[inline]
+    pub fn cmd_set_scissor(&self, extent: vk::Extent2D) {
+        let scissor = vk::Rect2D::default()
+            .offset(vk::Offset2D::default())
+            .extent(extent);
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_set_scissor(self.command_buffer_handle, 0, std::slice::from_ref(&scissor)); }
+    }
+
+    /// Sets both the dynamic viewport and scissor to cover the whole extent, which pipelines with dynamic viewport and scissor state need every frame.
+    #[inline]
+    pub fn cmd_set_viewport_scissor(&self, extent: vk::Extent2D) {
+        self.cmd_set_viewport(extent);
+        self.cmd_set_scissor(extent);
+    }
+
+    // Debug Labels
+
+    /// Begins a labeled region of the command buffer, which shows up in frame captures (e.g. RenderDoc or Nsight).
+    /// This does nothing if `VK_EXT_debug_utils` is disabled.
+    #[inline]
+    pub fn begin_debug_label(&self, name: &CStr, color: [f32; 4]) {
+        if let Some(debug_utils) = &self.debug_utils {
+            let label = vk::DebugUtilsLabelEXT::default()
+                .label_name(name)
+                .color(color);
+            // SAFETY: The device is available at this point.
+            unsafe { debug_utils.cmd_begin_debug_utils_label(self.command_buffer_handle, &label); }
+        }
+    }
+
+    #[inline]
+    pub fn end_debug_label(&self) {
+        if let Some(debug_utils) = &self.debug_utils {
+            // SAFETY: The device is available at this point.
+            unsafe { debug_utils.cmd_end_debug_utils_label(self.command_buffer_handle); }
+        }
+    }
+
+    /// Begins a labeled region that ends once the returned scope is dropped.
+    #[inline]
+    pub fn debug_label_scope(&self, name: &CStr, color: [f32; 4]) -> DebugLabelScope<'_> {
+        self.begin_debug_label(name, color);
+        DebugLabelScope { frame: self }
+    }
+
     // Utilities
 
+    #[inline]
+    pub fn cmd_pipeline_barrier2(&self, dependency_info: &vk::DependencyInfo) {
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.cmd_pipeline_barrier2(self.command_buffer_handle, dependency_info); }
+    }
+
     #[inline]
     pub fn transition_image(&self, image: &super::Image, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) -> VkResult<()> {
         self.transition_image_ex(
@@ -163,40 +480,88 @@ impl Drop for Frame {
         // SAFETY: The device is available at this point.
         unsafe {
             self.device.destroy_command_pool(self.command_pool_handle, None);
-            self.device.destroy_semaphore(self.swapchain_semaphore, None);
             self.device.destroy_semaphore(self.render_semaphore, None);
             self.device.destroy_fence(self.render_fence, None);
         }
     }
 }
 
+/// A debug label region of a frame's command buffer, which ends upon being dropped.
+pub struct DebugLabelScope<'a> {
+    frame: &'a Frame,
+}
+
+impl Drop for DebugLabelScope<'_> {
+    fn drop(&mut self) {
+        self.frame.end_debug_label();
+    }
+}
+
 /// A collection of frames to be rendered.
 pub struct Framebuffer {
     frames: [Frame; constants::FRAMEBUFFER_SIZE],
     command_pool_flags: vk::CommandPoolCreateFlags,
     queue_family_index: super::QueueFamilyIndex,
+    /// The timeline semaphore shared by every frame, if using [`FrameSync::Timeline`].
+    timeline_semaphore: Option<vk::Semaphore>,
     device: ash::Device,
+    debug_utils: Option<ext::debug_utils::Device>,
     current_frame: usize,
 }
 
+impl fmt::Debug for Framebuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Framebuffer")
+            .field("frames", &self.frames)
+            .field("queue_family_index", &self.queue_family_index)
+            .field("timeline_semaphore", &self.timeline_semaphore)
+            .field("current_frame", &self.current_frame)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Framebuffer {
-    pub(super) fn new(device: &super::Device, command_pool_flags: vk::CommandPoolCreateFlags, queue_family_index: super::QueueFamilyIndex) -> VkResult<Self> {
+    pub(super) fn new(device: &super::Device, command_pool_flags: vk::CommandPoolCreateFlags, queue_family_index: super::QueueFamilyIndex, sync: FrameSync) -> VkResult<Self> {
+        let timeline_semaphore = match sync {
+            FrameSync::Binary => None,
+            FrameSync::Timeline => {
+                let mut type_create_info = vk::SemaphoreTypeCreateInfo::default()
+                    .semaphore_type(vk::SemaphoreType::TIMELINE)
+                    .initial_value(0);
+                let create_info = vk::SemaphoreCreateInfo::default()
+                    .push_next(&mut type_create_info);
+                // SAFETY: The object is destroyed upon dropping the framebuffer.
+                Some(unsafe { device.inner.create_semaphore(&create_info, None)? })
+            },
+        };
+        let frames = match Framebuffer::_flush(&device.inner, &device.debug_utils, command_pool_flags, queue_family_index, timeline_semaphore) {
+            Ok(frames) => frames,
+            Err(e) => {
+                if let Some(timeline_semaphore) = timeline_semaphore {
+                    // SAFETY: No frame uses the semaphore yet.
+                    unsafe { device.inner.destroy_semaphore(timeline_semaphore, None); }
+                }
+                return Err(e)
+            },
+        };
         Ok(
             Self {
-                frames: Framebuffer::_flush(&device.inner, command_pool_flags, queue_family_index)?,
+                frames,
                 command_pool_flags,
                 queue_family_index,
+                timeline_semaphore,
                 device: device.inner.clone(),
+                debug_utils: device.debug_utils.clone(),
                 current_frame: 0,
             }
         )
     }
 
-    fn _flush(device: &ash::Device, command_pool_flags: vk::CommandPoolCreateFlags, queue_family_index: super::QueueFamilyIndex) -> VkResult<[Frame; constants::FRAMEBUFFER_SIZE]> {
+    fn _flush(device: &ash::Device, debug_utils: &Option<ext::debug_utils::Device>, command_pool_flags: vk::CommandPoolCreateFlags, queue_family_index: super::QueueFamilyIndex, timeline_semaphore: Option<vk::Semaphore>) -> VkResult<[Frame; constants::FRAMEBUFFER_SIZE]> {
         let mut frames = [const { MaybeUninit::uninit() }; constants::FRAMEBUFFER_SIZE];
         for (i, elem) in frames.iter_mut().enumerate() {
             // SAFETY: handle errors ourself so that we don't memory leak any already-initialized elements.
-            match Frame::new(device.clone(), command_pool_flags, queue_family_index) {
+            match Frame::new(device.clone(), debug_utils.clone(), command_pool_flags, queue_family_index, timeline_semaphore) {
                 Ok(frame) => {
                     elem.write(frame);
                 },
@@ -215,15 +580,36 @@ impl Framebuffer {
         Ok(frames)
     }
 
+    /// Rebuilds every frame's command pool, semaphores, and fence, discarding any stale synchronization state.
+    /// This must run whenever the frames' semaphores may have been left pending, such as after recreating the swapchain.
+    /// It waits for the device to idle first, since the old frames must not be in flight when they're destroyed.
     pub fn flush(&mut self) -> VkResult<()> {
-        let frames = Framebuffer::_flush(&self.device, self.command_pool_flags, self.queue_family_index)?;
+        // SAFETY: The device is available at this point.
+        unsafe { self.device.device_wait_idle()?; }
+        // The timeline semaphore is kept, since its value only increases and the device has idled, so the new frames needn't wait on it.
+        let frames = Framebuffer::_flush(&self.device, &self.debug_utils, self.command_pool_flags, self.queue_family_index, self.timeline_semaphore)?;
         self.frames = frames;
         Ok(())
     }
 
     #[inline]
     pub fn current_frame(&self) -> &Frame {
-        &self.frames[self.current_frame % constants::FRAMEBUFFER_SIZE]
+        &self.frames[self.frame_index()]
+    }
+
+    /// The current frame, mutably, e.g. to allocate command buffers or recreate its command pool.
+    #[inline]
+    pub fn current_frame_mut(&mut self) -> &mut Frame {
+        let frame_index = self.frame_index();
+        &mut self.frames[frame_index]
+    }
+
+    /// The index of the current frame in flight, which selects per-frame resources.
+    /// This is *not* the swapchain image index, which is decided by the presentation engine upon acquiring an image
+    /// and must be used for per-image resources instead.
+    #[inline]
+    pub fn frame_index(&self) -> usize {
+        self.current_frame % constants::FRAMEBUFFER_SIZE
     }
 
     #[inline]
@@ -235,4 +621,19 @@ impl Framebuffer {
     pub fn current_frame_count(&self) -> usize {
         self.current_frame
     }
+
+    /// The value that the current frame's submission signals the timeline semaphore with, which increases every frame.
+    #[inline]
+    pub fn timeline_value(&self) -> u64 {
+        self.current_frame as u64 + 1
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        if let Some(timeline_semaphore) = self.timeline_semaphore {
+            // SAFETY: The frames are no longer in flight upon dropping the framebuffer.
+            unsafe { self.device.destroy_semaphore(timeline_semaphore, None); }
+        }
+    }
 }
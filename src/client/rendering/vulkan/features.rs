@@ -0,0 +1,178 @@
+//! # Device Features
+//! A single place to register the device features enabled at device creation.
+
+use std::{mem::size_of, ptr};
+
+use ash::vk;
+
+use crate::constants;
+
+macro_rules! core_features {
+    ( $( $variant:ident => $field:ident ),* $(,)? ) => {
+        /// A core device feature, by which required features are listed in [`constants::REQUIRED_CORE_FEATURES`].
+        /// To require a feature that isn't listed here, add it to the invocation of `core_features!`.
+        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+        pub enum CoreFeature {
+            $( $variant, )*
+        }
+
+        impl CoreFeature {
+            pub const ALL: &'static [Self] = &[$( Self::$variant, )*];
+
+            /// The name of the feature's field in [`vk::PhysicalDeviceFeatures`].
+            pub fn name(self) -> &'static str {
+                match self {
+                    $( Self::$variant => stringify!($field), )*
+                }
+            }
+
+            pub fn is_enabled(self, features: &vk::PhysicalDeviceFeatures) -> bool {
+                match self {
+                    $( Self::$variant => features.$field == vk::TRUE, )*
+                }
+            }
+
+            pub fn enable(self, features: &mut vk::PhysicalDeviceFeatures) {
+                match self {
+                    $( Self::$variant => features.$field = vk::TRUE, )*
+                }
+            }
+        }
+    };
+}
+
+core_features! {
+    GeometryShader => geometry_shader,
+    TessellationShader => tessellation_shader,
+    FillModeNonSolid => fill_mode_non_solid,
+    WideLines => wide_lines,
+    SamplerAnisotropy => sampler_anisotropy,
+    MultiDrawIndirect => multi_draw_indirect,
+    DepthClamp => depth_clamp,
+    ShaderInt64 => shader_int64,
+}
+
+impl CoreFeature {
+    /// Enables each of `core_features`.
+    pub fn features(core_features: &[Self]) -> vk::PhysicalDeviceFeatures {
+        let mut features = vk::PhysicalDeviceFeatures::default();
+        core_features.iter().for_each(|core_feature| core_feature.enable(&mut features));
+        features
+    }
+}
+
+/// A set of core and extended device features.
+/// To enable an additional core feature, list it in [`constants::REQUIRED_CORE_FEATURES`]; to enable an extended feature, set it in [`DeviceFeatures::required`].
+/// To enable a feature struct that isn't registered yet, add it as a field and link it in [`DeviceFeatures::features2`].
+#[derive(Clone, Copy, Default)]
+pub struct DeviceFeatures {
+    pub core: vk::PhysicalDeviceFeatures,
+    pub vulkan_1_3: vk::PhysicalDeviceVulkan13Features<'static>,
+    /// Timeline semaphores are core in Vulkan 1.2, but optional for [`FrameSync::Timeline`](super::commands::FrameSync::Timeline).
+    pub timeline_semaphore: vk::PhysicalDeviceTimelineSemaphoreFeatures<'static>,
+}
+
+impl DeviceFeatures {
+    /// The features required by the renderer.
+    pub fn required() -> Self {
+        Self {
+            core: *constants::ENABLED_DEVICE_FEATURES,
+            vulkan_1_3: vk::PhysicalDeviceVulkan13Features::default()
+                .synchronization2(true)
+                .dynamic_rendering(true),
+            timeline_semaphore: Default::default(),
+        }
+    }
+
+    /// Queries the features supported by a physical device.
+    pub fn query(instance: &super::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mut features = Self::default();
+        let mut features2 = features.features2();
+        instance.get_physical_device_features2(physical_device, &mut features2);
+        let core = features2.features;
+        features.core = core;
+        features
+    }
+
+    /// Checks whether every feature enabled in `required` is also enabled in `self`.
+    pub fn supports(&self, required: &Self) -> bool {
+        supports_all(core_bools(&self.core), core_bools(&required.core))
+            && supports_all(&vulkan_1_3_bools(&self.vulkan_1_3), &vulkan_1_3_bools(&required.vulkan_1_3))
+            && supports_all(&[self.timeline_semaphore.timeline_semaphore], &[required.timeline_semaphore.timeline_semaphore])
+    }
+
+    /// Lists the names of the features enabled in `required` that aren't enabled in `self`, for logging why a device is unsuitable.
+    pub fn unsupported(&self, required: &Self) -> Vec<&'static str> {
+        let core = CoreFeature::ALL
+            .iter()
+            .filter(|core_feature| core_feature.is_enabled(&required.core) && !core_feature.is_enabled(&self.core))
+            .map(|core_feature| core_feature.name());
+        let vulkan_1_3 = VULKAN_1_3_NAMES
+            .into_iter()
+            .zip(vulkan_1_3_bools(&self.vulkan_1_3).into_iter().zip(vulkan_1_3_bools(&required.vulkan_1_3)))
+            .filter(|(_, (supported, required))| *required == vk::TRUE && *supported == vk::FALSE)
+            .map(|(name, _)| name);
+        let timeline_semaphore = (required.timeline_semaphore.timeline_semaphore == vk::TRUE && self.timeline_semaphore.timeline_semaphore == vk::FALSE)
+            .then_some("timeline_semaphore");
+        core.chain(vulkan_1_3).chain(timeline_semaphore).collect()
+    }
+
+    /// Links the feature structs into a chain for querying features or creating a device.
+    pub fn features2(&mut self) -> vk::PhysicalDeviceFeatures2<'_> {
+        // Unlink any stale chain from a previous call, since `push_next` follows existing links.
+        self.vulkan_1_3.p_next = ptr::null_mut();
+        self.timeline_semaphore.p_next = ptr::null_mut();
+        vk::PhysicalDeviceFeatures2::default()
+            .features(self.core)
+            .push_next(&mut self.vulkan_1_3)
+            .push_next(&mut self.timeline_semaphore)
+    }
+}
+
+fn supports_all(supported: &[vk::Bool32], required: &[vk::Bool32]) -> bool {
+    supported.iter().zip(required).all(|(supported, required)| *required == vk::FALSE || *supported == vk::TRUE)
+}
+
+fn core_bools(features: &vk::PhysicalDeviceFeatures) -> &[vk::Bool32] {
+    // SAFETY: `vk::PhysicalDeviceFeatures` is `repr(C)` and consists solely of `vk::Bool32`s.
+    unsafe { std::slice::from_raw_parts(features as *const _ as *const vk::Bool32, size_of::<vk::PhysicalDeviceFeatures>() / size_of::<vk::Bool32>()) }
+}
+
+/// The names of the fields returned by [`vulkan_1_3_bools`], in the same order.
+const VULKAN_1_3_NAMES: [&'static str; 15] = [
+    "robust_image_access",
+    "inline_uniform_block",
+    "descriptor_binding_inline_uniform_block_update_after_bind",
+    "pipeline_creation_cache_control",
+    "private_data",
+    "shader_demote_to_helper_invocation",
+    "shader_terminate_invocation",
+    "subgroup_size_control",
+    "compute_full_subgroups",
+    "synchronization2",
+    "texture_compression_astc_hdr",
+    "shader_zero_initialize_workgroup_memory",
+    "dynamic_rendering",
+    "shader_integer_dot_product",
+    "maintenance4",
+];
+
+fn vulkan_1_3_bools(features: &vk::PhysicalDeviceVulkan13Features) -> [vk::Bool32; 15] {
+    [
+        features.robust_image_access,
+        features.inline_uniform_block,
+        features.descriptor_binding_inline_uniform_block_update_after_bind,
+        features.pipeline_creation_cache_control,
+        features.private_data,
+        features.shader_demote_to_helper_invocation,
+        features.shader_terminate_invocation,
+        features.subgroup_size_control,
+        features.compute_full_subgroups,
+        features.synchronization2,
+        features.texture_compression_astc_hdr,
+        features.shader_zero_initialize_workgroup_memory,
+        features.dynamic_rendering,
+        features.shader_integer_dot_product,
+        features.maintenance4,
+    ]
+}
@@ -1,19 +1,54 @@
 //! # Allocated Image
 //! A custom image separate from the swapchain.
 
+use std::{cell::Cell, fmt};
+
 use ash::{prelude::VkResult, vk};
 
+use crate::client::rendering::RenderResult;
+
 pub struct AllocatedImage {
     image: super::Image,
     image_view: super::ImageView,
     extent: vk::Extent3D,
     format: vk::Format,
+    /// The number of mip levels, which is the full chain if mipmaps are generated.
+    mip_levels: u32,
+    generate_mipmaps: bool,
+    /// The layout of the image as of the most recently recorded transition.
+    layout: Cell<vk::ImageLayout>,
+    /// The info the image was created with, which is reused upon resizing.
+    image_create_info: vk::ImageCreateInfo<'static>,
+    image_view_create_info: vk::ImageViewCreateInfo<'static>,
     device: ash::Device,
 }
 
+impl fmt::Debug for AllocatedImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllocatedImage")
+            .field("image", &self.image)
+            .field("image_view", &self.image_view)
+            .field("extent", &self.extent)
+            .field("format", &self.format)
+            .field("mip_levels", &self.mip_levels)
+            .field("layout", &self.layout.get())
+            .finish_non_exhaustive()
+    }
+}
+
 impl AllocatedImage {
-    pub(super) fn new(device: &super::Device, image_create_info: &vk::ImageCreateInfo, image_view_create_info: &vk::ImageViewCreateInfo, extent: vk::Extent3D, format: vk::Format) -> VkResult<Self> {
-        let image = device.create_image(image_create_info)?;
+    /// Creates an image and a view of it.
+    /// If `generate_mipmaps` is set, the image is created with a full mip chain that can be filled by [`Self::generate_mipmaps`],
+    /// overriding the create info's mip level count; otherwise, the create info's mip level count is kept.
+    pub(super) fn new(device: &super::Device, image_create_info: &vk::ImageCreateInfo, image_view_create_info: &vk::ImageViewCreateInfo, extent: vk::Extent3D, format: vk::Format, generate_mipmaps: bool) -> RenderResult<Self> {
+        let mut image_create_info = detach_image_create_info(image_create_info);
+        if generate_mipmaps {
+            // Mipmaps are generated by blitting each level from the last.
+            image_create_info = image_create_info
+                .mip_levels(super::util::mip_levels(image_create_info.extent))
+                .usage(image_create_info.usage | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST);
+        }
+        let image = device.create_image(&image_create_info)?;
         let image_view_create_info = image_view_create_info
             .image(*image);
         let image_view = device.create_image_view(&image_view_create_info)?;
@@ -23,11 +58,38 @@ impl AllocatedImage {
                 image_view,
                 extent,
                 format,
+                mip_levels: image_create_info.mip_levels,
+                generate_mipmaps,
+                layout: Cell::new(vk::ImageLayout::UNDEFINED),
+                image_create_info,
+                image_view_create_info: detach_image_view_create_info(&image_view_create_info),
                 device: device.inner.clone(),
             }
         )
     }
 
+    /// Replaces the image and its view with ones of the same format and usage at `extent`, discarding the image's contents.
+    /// The GPU must no longer be using the old image.
+    pub fn resize(&mut self, device: &super::Device, extent: vk::Extent3D) -> RenderResult<()> {
+        let mut image_create_info = self.image_create_info
+            .extent(extent);
+        if self.generate_mipmaps {
+            image_create_info = image_create_info.mip_levels(super::util::mip_levels(extent));
+        }
+        let image = device.create_image(&image_create_info)?;
+        let image_view_create_info = self.image_view_create_info
+            .image(*image);
+        let image_view = device.create_image_view(&image_view_create_info)?;
+        // Drop the old view before the old image it references.
+        self.image_view = image_view;
+        self.image = image;
+        self.image_create_info = image_create_info;
+        self.extent = extent;
+        self.mip_levels = image_create_info.mip_levels;
+        self.layout.set(vk::ImageLayout::UNDEFINED);
+        Ok(())
+    }
+
     #[inline]
     pub fn image(&self) -> &super::Image {
         &self.image
@@ -42,4 +104,121 @@ impl AllocatedImage {
     pub fn extent(&self) -> vk::Extent3D {
         self.extent
     }
+
+    #[inline]
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    #[inline]
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    #[inline]
+    pub fn array_layers(&self) -> u32 {
+        self.image_create_info.array_layers
+    }
+
+    #[inline]
+    pub fn layout(&self) -> vk::ImageLayout {
+        self.layout.get()
+    }
+
+    /// Transitions the image from its tracked layout to `new_layout`.
+    pub fn transition_to(&self, frame: &super::commands::Frame, new_layout: vk::ImageLayout) -> VkResult<()> {
+        self.transition(frame, self.layout(), new_layout)
+    }
+
+    /// Transitions the image with an explicit old layout, which must match the tracked layout.
+    /// Transitioning from [`vk::ImageLayout::UNDEFINED`] is always allowed, since it discards the image's contents.
+    pub fn transition(&self, frame: &super::commands::Frame, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) -> VkResult<()> {
+        debug_assert!(
+            old_layout == vk::ImageLayout::UNDEFINED || old_layout == self.layout(),
+            "image transition from {old_layout:?} disagrees with the tracked layout {:?}", self.layout(),
+        );
+        frame.transition_image(&self.image, old_layout, new_layout)?;
+        self.layout.set(new_layout);
+        Ok(())
+    }
+
+    /// Fills every mip level by successively blitting each level to the next at half its size,
+    /// leaving the whole image in the [`vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL`] layout for sampling.
+    /// The base level must have been written, and the image must be in the [`vk::ImageLayout::TRANSFER_DST_OPTIMAL`] layout.
+    pub fn generate_mipmaps(&self, frame: &super::commands::Frame) {
+        debug_assert_eq!(self.layout(), vk::ImageLayout::TRANSFER_DST_OPTIMAL, "mipmaps must be generated from the transfer destination layout");
+        let level_barrier = |mip_level: u32, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout, src_access_mask: vk::AccessFlags2, dst_access_mask: vk::AccessFlags2, dst_stage_mask: vk::PipelineStageFlags2| {
+            let subresource_range = super::util::image_subresource_range(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(mip_level)
+                .level_count(1);
+            let image_barrier = vk::ImageMemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(src_access_mask)
+                .dst_stage_mask(dst_stage_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .subresource_range(subresource_range)
+                .image(*self.image);
+            frame.cmd_pipeline_barrier2(&vk::DependencyInfo::default().image_memory_barriers(std::slice::from_ref(&image_barrier)));
+        };
+        let mip_extent = |mip_level: u32| vk::Extent3D {
+            width: (self.extent.width >> mip_level).max(1),
+            height: (self.extent.height >> mip_level).max(1),
+            depth: (self.extent.depth >> mip_level).max(1),
+        };
+
+        for mip_level in 1..self.mip_levels {
+            // The previous level has just been written, so it can now be read from.
+            level_barrier(mip_level - 1, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::AccessFlags2::TRANSFER_WRITE, vk::AccessFlags2::TRANSFER_READ, vk::PipelineStageFlags2::TRANSFER);
+            // Every layer is blitted at once, e.g. each face of a cubemap.
+            let src = super::util::BlitRegion {
+                image: &self.image,
+                size: mip_extent(mip_level - 1),
+                subresource: super::util::image_subresource_layers(vk::ImageAspectFlags::COLOR).mip_level(mip_level - 1).layer_count(self.array_layers()),
+            };
+            let dst = super::util::BlitRegion {
+                image: &self.image,
+                size: mip_extent(mip_level),
+                subresource: super::util::image_subresource_layers(vk::ImageAspectFlags::COLOR).mip_level(mip_level).layer_count(self.array_layers()),
+            };
+            super::util::blit_image(frame, &src, &dst, vk::Filter::LINEAR);
+        }
+
+        // Every level but the last has been read from, and the last has only been written to.
+        let last_level = self.mip_levels - 1;
+        for mip_level in 0..last_level {
+            level_barrier(mip_level, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::AccessFlags2::TRANSFER_READ, vk::AccessFlags2::SHADER_SAMPLED_READ, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        }
+        level_barrier(last_level, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::AccessFlags2::TRANSFER_WRITE, vk::AccessFlags2::SHADER_SAMPLED_READ, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        self.layout.set(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    }
+}
+
+/// Copies the create info without its pointer chain and queue family indices, which may not outlive it.
+fn detach_image_create_info(create_info: &vk::ImageCreateInfo) -> vk::ImageCreateInfo<'static> {
+    debug_assert!(create_info.p_next.is_null() && create_info.queue_family_index_count == 0, "resizable images must not have extension structs or shared queue families");
+    vk::ImageCreateInfo::default()
+        .flags(create_info.flags)
+        .image_type(create_info.image_type)
+        .format(create_info.format)
+        .extent(create_info.extent)
+        .mip_levels(create_info.mip_levels)
+        .array_layers(create_info.array_layers)
+        .samples(create_info.samples)
+        .tiling(create_info.tiling)
+        .usage(create_info.usage)
+        .sharing_mode(create_info.sharing_mode)
+        .initial_layout(create_info.initial_layout)
+}
+
+/// Copies the create info without its pointer chain or image.
+fn detach_image_view_create_info(create_info: &vk::ImageViewCreateInfo) -> vk::ImageViewCreateInfo<'static> {
+    debug_assert!(create_info.p_next.is_null(), "resizable image views must not have extension structs");
+    vk::ImageViewCreateInfo::default()
+        .flags(create_info.flags)
+        .view_type(create_info.view_type)
+        .format(create_info.format)
+        .components(create_info.components)
+        .subresource_range(create_info.subresource_range)
 }
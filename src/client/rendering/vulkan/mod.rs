@@ -2,15 +2,21 @@
 //! This module provides safe abstractions for Vulkan objects.
 //!
 //! See [`VulkanObject`] and [`Instance`].
+//!
+//! # Threading
+//! [`Instance`] and the objects it owns are neither `Send` nor `Sync`, since the allocator is shared via [`Rc`].
+//! All rendering happens on the thread that created the instance (i.e. the event loop thread).
+//! Work may be offloaded to other threads only if it doesn't touch these objects, e.g. by sending plain data back over a channel.
 
-use std::{any::Any, borrow::BorrowMut, collections::HashMap, mem::ManuallyDrop, ops::Deref, path::PathBuf, ptr::drop_in_place, rc::Rc};
+use std::{any::Any, borrow::BorrowMut, collections::HashMap, ffi::CStr, mem::ManuallyDrop, ops::Deref, path::PathBuf, ptr::drop_in_place, rc::Rc, thread::ThreadId};
 
 use ash::{ext, khr, prelude::VkResult, vk};
-use sigill_derive::{Deref, DerefMut};
+use sigill_derive::{Deref, DerefDebug, DerefMut};
 use vk_mem::Alloc;
 use winit::raw_window_handle::{RawDisplayHandle, RawWindowHandle};
 
 use super::RenderResult;
+use crate::info;
 
 pub mod swapchain;
 pub mod pipeline;
@@ -19,6 +25,8 @@ pub mod commands;
 pub mod util;
 pub mod queues;
 pub mod image;
+pub mod buffer;
+pub mod features;
 
 pub type QueueFamilyIndex = u32;
 pub type QueueIndex = u32;
@@ -30,7 +38,7 @@ pub type QueueIndex = u32;
 /// This type serves as a utility for automatically destroying each Vulkan object upon being dropped.
 /// 
 /// See [`VulkanObjectType`].
-#[derive(Deref, DerefMut)]
+#[derive(Deref, DerefMut, DerefDebug)]
 pub struct VulkanObject<T, D>(T, D, fn(&T, &mut D));
 
 impl<T, D> VulkanObject<T, D> {
@@ -55,15 +63,35 @@ impl<T, D> Drop for VulkanObject<T, D> {
 pub type DebugUtilsMessenger = VulkanObject<vk::DebugUtilsMessengerEXT, ext::debug_utils::Instance>;
 pub type Surface = VulkanObject<vk::SurfaceKHR, khr::surface::Instance>;
 pub type ImageView = VulkanObject<vk::ImageView, ash::Device>;
+pub type Sampler = VulkanObject<vk::Sampler, ash::Device>;
+pub type PipelineLayout = VulkanObject<vk::PipelineLayout, ash::Device>;
+pub type Pipeline = VulkanObject<vk::Pipeline, ash::Device>;
+pub type Fence = VulkanObject<vk::Fence, ash::Device>;
+pub type Semaphore = VulkanObject<vk::Semaphore, ash::Device>;
 pub type Image = VulkanObject<vk::Image, Option<(Rc<vk_mem::Allocator>, vk_mem::Allocation)>>;
+pub type Buffer = VulkanObject<vk::Buffer, Option<(Rc<vk_mem::Allocator>, vk_mem::Allocation)>>;
 
 /// A type of Vulkan object that is automatically dropped in order of dependency.
 /// # Safety
 /// All object types must declared be below their dependents since objects are dropped in the order of their discriminant.
 #[repr(u32)]
-#[derive(Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum VulkanObjectType {
-    TriangleShader,
+    // Shader modules are only needed while creating pipelines. See [`shader::ShaderRegistry`].
+    Shaders,
+
+    // Descriptor sets may reference samplers, buffers, and images, so free them first.
+    DescriptorPool,
+
+    // Pipeline layouts are only referenced by pipelines and their command buffers.
+    PipelineLayout,
+
+    PipelineCache,
+
+    // Samplers and buffers are referenced by descriptor sets and command buffers, but are independent of the images below.
+    Sampler,
+
+    Buffer,
 
     DrawImage,
 
@@ -79,27 +107,85 @@ pub enum VulkanObjectType {
     DebugUtilsMessenger,
 }
 
+impl VulkanObjectType {
+    /// Every object type in drop order.
+    pub const ALL: &'static [Self] = &[
+        Self::Shaders,
+        Self::DescriptorPool,
+        Self::PipelineLayout,
+        Self::PipelineCache,
+        Self::Sampler,
+        Self::Buffer,
+        Self::DrawImage,
+        Self::Framebuffer,
+        Self::Swapchain,
+        Self::Surface,
+        Self::Device,
+        Self::DebugUtilsMessenger,
+    ];
+}
+
+// Ensure that no object created from the device is accidentally dropped after it.
+const _: () = {
+    assert!(VulkanObjectType::ALL.len() == VulkanObjectType::DebugUtilsMessenger as usize + 1, "VulkanObjectType::ALL must list every variant");
+    let mut i = 0;
+    while i < VulkanObjectType::ALL.len() {
+        let object_type = VulkanObjectType::ALL[i];
+        assert!(
+            object_type as u32 == i as u32,
+            "VulkanObjectType::ALL must list every variant in drop order",
+        );
+        assert!(
+            object_type as u32 <= VulkanObjectType::Device as u32 || matches!(object_type, VulkanObjectType::DebugUtilsMessenger),
+            "Vulkan objects must be dropped before the device",
+        );
+        i += 1;
+    }
+};
+
 /// The struct that owns all Vulkan objects.
+/// It must only be used from the thread that created it; see the [module documentation](self#threading).
 pub struct Instance {
     /// An abstraction for handling inherited Vulkan objects.
     objects: ManuallyDrop<HashMap<VulkanObjectType, Box<dyn Any>>>,
+    /// The thread that created the instance, which is checked against in debug builds.
+    owner_thread: ThreadId,
     extensions: Extensions,
     inner: ash::Instance,
     entry: ash::Entry,
 }
 
+impl std::fmt::Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The objects are type-erased, so only list which ones exist.
+        let mut object_types = self.objects.keys().collect::<Vec<_>>();
+        object_types.sort();
+        f.debug_struct("Instance")
+            .field("handle", &self.inner.handle())
+            .field("object_types", &object_types)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Instance {
     pub fn new(entry: ash::Entry, instance_info: &vk::InstanceCreateInfo) -> RenderResult<Self> {
         // SAFETY: The object is automatically dropped.
         let inner = unsafe { entry.create_instance(instance_info, None)?};
         Ok(Self {
             objects: ManuallyDrop::new(HashMap::new()),
+            owner_thread: std::thread::current().id(),
             extensions: Extensions::new(&entry, &inner),
             inner,
             entry,
         })
     }
 
+    /// The raw instance handle, for interop with libraries that use Vulkan directly.
+    #[inline]
+    pub fn handle(&self) -> &ash::Instance {
+        &self.inner
+    }
+
     // Vulkan Object Management
 
     #[inline]
@@ -112,6 +198,11 @@ impl Instance {
         self.get_object(VulkanObjectType::DrawImage).expect("draw_image must be initialized before being accessed")
     }
 
+    #[inline]
+    pub fn pipeline_cache(&self) -> &pipeline::PipelineCache {
+        self.get_object(VulkanObjectType::PipelineCache).expect("pipeline_cache must be initialized before being accessed")
+    }
+
     #[inline]
     pub fn framebuffer(&self) -> &commands::Framebuffer {
         self.get_object(VulkanObjectType::Framebuffer).expect("framebuffer must be initialized before being accessed")
@@ -140,18 +231,40 @@ impl Instance {
     // TODO: Implement deque-based Vulkan object destruction system.
     #[inline]
     pub fn get_object<T: Any>(&self, object_type: VulkanObjectType) -> Option<&T> {
+        self.debug_assert_owner_thread();
         self.objects.get(&object_type)?.downcast_ref()
     }
 
     #[inline]
     pub fn get_object_mut<T: Any>(&mut self, object_type: VulkanObjectType) -> Option<&mut T> {
+        self.debug_assert_owner_thread();
         self.objects.get_mut(&object_type)?.downcast_mut()
     }
 
+    /// Whether an object of this type is present, for optional objects that aren't always created.
+    #[inline]
+    pub fn has_object(&self, object_type: VulkanObjectType) -> bool {
+        self.debug_assert_owner_thread();
+        self.objects.contains_key(&object_type)
+    }
+
     pub fn set_object<T: Any>(&mut self, object_type: VulkanObjectType, object: T) {
+        self.debug_assert_owner_thread();
         self.objects.insert(object_type, Box::new(object));
     }
 
+    /// Destroys the object, if present. The GPU must no longer be using it.
+    pub fn destroy_object(&mut self, object_type: VulkanObjectType) {
+        self.debug_assert_owner_thread();
+        self.objects.remove(&object_type);
+    }
+
+    /// Panics in debug builds if called from a thread other than the one that created the instance.
+    #[inline]
+    fn debug_assert_owner_thread(&self) {
+        debug_assert_eq!(std::thread::current().id(), self.owner_thread, "the Vulkan instance must only be used from the thread that created it");
+    }
+
     #[inline]
     pub fn entry(&self) -> &ash::Entry {
         &self.entry
@@ -205,32 +318,25 @@ impl Instance {
     #[inline]
     pub fn create_swapchain<'a>(&mut self, create_info: &vk::SwapchainCreateInfoKHR, image_view_provider: impl FnOnce(&Vec<Image>, vk::Format) -> Vec<vk::ImageViewCreateInfo<'a>>) -> VkResult<&swapchain::Swapchain> {
         let swapchain_device = khr::swapchain::Device::new(&self.inner, &self.device().inner);
-        // SAFETY: The object is automatically dropped.
         self.set_object(
             VulkanObjectType::Swapchain,
-            unsafe {
-                let handle = swapchain_device.create_swapchain(create_info, None)?;
-                let images = swapchain_device.get_swapchain_images(handle)?
-                    .into_iter()
-                    .map(|image| VulkanObject::undropped(image))
-                    .collect::<Vec<_>>();
-                let image_view = image_view_provider(&images, create_info.image_format)
-                    .into_iter()
-                    .map(|create_info| self.device().create_image_view(&create_info))
-                    .collect::<Result<Vec<_>, _>>()?;
-                swapchain::Swapchain::new(
-                    handle,
-                    swapchain_device,
-                    images,
-                    image_view,
-                    create_info.image_format,
-                    create_info.image_extent.into(),
-                )
-            }
+            swapchain::Swapchain::create(self.device(), swapchain_device, create_info, image_view_provider)?,
         );
         Ok(self.swapchain())
     }
 
+    /// Replaces the swapchain with a new one created from the old one, or creates one if absent.
+    /// The device must be idle.
+    pub fn recreate_swapchain<'a>(&mut self, create_info: &vk::SwapchainCreateInfoKHR, image_view_provider: impl FnOnce(&Vec<Image>, vk::Format) -> Vec<vk::ImageViewCreateInfo<'a>>) -> VkResult<&swapchain::Swapchain> {
+        let Some(old_swapchain) = self.get_object::<swapchain::Swapchain>(VulkanObjectType::Swapchain) else {
+            return self.create_swapchain(create_info, image_view_provider)
+        };
+        let new_swapchain = old_swapchain.recreate(self.device(), create_info, image_view_provider)?;
+        // The old swapchain is dropped upon being replaced.
+        self.set_object(VulkanObjectType::Swapchain, new_swapchain);
+        Ok(self.swapchain())
+    }
+
     // Vulkan Object Creation
     
     #[inline]
@@ -260,43 +366,123 @@ impl Instance {
         );
         // SAFETY: The object is automatically dropped.
         let allocator = unsafe { vk_mem::Allocator::new(allocator_create_info)? };
+        let debug_utils = Self::is_extension_enabled(ext::debug_utils::NAME)
+            .then(|| ext::debug_utils::Device::new(&self.inner, &device));
         self.set_object(
             VulkanObjectType::Device,
             Device {
                 inner: device,
                 allocator: Rc::new(allocator),
+                debug_utils,
             },
         );
         Ok(self.device())
     }
 
+    /// The loaded shaders, if any have been loaded.
     #[inline]
-    fn create_shader_module(&mut self, object_type: VulkanObjectType, create_info: &vk::ShaderModuleCreateInfo, path: PathBuf) -> VkResult<&shader::ShaderModule> {
-        self.set_object(
-            object_type,
-            shader::ShaderModule::new(self.device().inner.clone(), create_info, path),
-        );
-        Ok(self.get_object(object_type).unwrap())
+    pub fn shaders(&self) -> Option<&shader::ShaderRegistry> {
+        self.get_object(VulkanObjectType::Shaders)
     }
 
+    /// The shader loaded under `name`, if any.
     #[inline]
-    pub fn create_framebuffer(&mut self, command_pool_flags: vk::CommandPoolCreateFlags, queue_family_index: QueueFamilyIndex) -> VkResult<&commands::Framebuffer> {
+    pub fn shader(&self, name: &str) -> Option<&shader::ShaderModule> {
+        self.shaders()?.get(name)
+    }
+
+    fn shaders_mut(&mut self) -> &mut shader::ShaderRegistry {
+        if !self.has_object(VulkanObjectType::Shaders) {
+            self.set_object(VulkanObjectType::Shaders, shader::ShaderRegistry::default());
+        }
+        self.get_object_mut(VulkanObjectType::Shaders).unwrap()
+    }
+
+    #[inline]
+    fn create_shader_module(&mut self, name: &str, create_info: &vk::ShaderModuleCreateInfo, path: PathBuf) -> VkResult<&shader::ShaderModule> {
+        let shader_module = shader::ShaderModule::new(self.device().inner.clone(), create_info, path)?;
+        Ok(self.shaders_mut().insert(name, shader_module))
+    }
+
+    /// Loads a compiled shader by its source name, e.g. `triangle.vert`, and registers it under that name.
+    /// See [`assets::resolve_shader`](crate::assets::resolve_shader).
+    pub fn load_shader(&mut self, name: &str) -> RenderResult<&shader::ShaderModule> {
+        let path = crate::assets::resolve_shader(name)?;
+        let shader_module = shader::ShaderModule::from_spv_path(self.device().inner.clone(), path)?;
+        Ok(self.shaders_mut().insert(name, shader_module))
+    }
+
+    #[inline]
+    pub fn create_framebuffer(&mut self, command_pool_flags: vk::CommandPoolCreateFlags, queue_family_index: QueueFamilyIndex, sync: commands::FrameSync) -> VkResult<&commands::Framebuffer> {
         self.set_object(
             VulkanObjectType::Framebuffer,
-            commands::Framebuffer::new(self.device(), command_pool_flags, queue_family_index)?,
+            commands::Framebuffer::new(self.device(), command_pool_flags, queue_family_index, sync)?,
        );
        Ok(self.framebuffer())
     }
 
     #[inline]
-    pub fn create_draw_image(&mut self, image_create_info: &vk::ImageCreateInfo, image_view_create_info: &vk::ImageViewCreateInfo, extent: vk::Extent3D, format: vk::Format) -> VkResult<&image::AllocatedImage> {
+    pub fn create_pipeline_cache(&mut self, properties: &vk::PhysicalDeviceProperties, path: PathBuf) -> VkResult<&pipeline::PipelineCache> {
+        self.set_object(
+            VulkanObjectType::PipelineCache,
+            pipeline::PipelineCache::new(self.device().inner.clone(), properties, path)?,
+        );
+        Ok(self.pipeline_cache())
+    }
+
+    #[inline]
+    pub fn create_draw_image(&mut self, image_create_info: &vk::ImageCreateInfo, image_view_create_info: &vk::ImageViewCreateInfo, extent: vk::Extent3D, format: vk::Format) -> RenderResult<&image::AllocatedImage> {
         self.set_object(
             VulkanObjectType::DrawImage,
-            image::AllocatedImage::new(self.device(), image_create_info, image_view_create_info, extent, format)?,
+            image::AllocatedImage::new(self.device(), image_create_info, image_view_create_info, extent, format, false)?,
         );
         Ok(self.draw_image())
     }
 
+    /// Resizes the draw image in place. The GPU must no longer be using it.
+    pub fn resize_draw_image(&mut self, extent: vk::Extent3D) -> RenderResult<&image::AllocatedImage> {
+        self.debug_assert_owner_thread();
+        // Take the draw image out temporarily, since resizing it borrows the device from the same map.
+        let mut draw_image = self.objects.remove(&VulkanObjectType::DrawImage)
+            .and_then(|object| object.downcast::<image::AllocatedImage>().ok())
+            .expect("draw_image must be initialized before being accessed");
+        let result = draw_image.resize(self.device(), extent);
+        self.objects.insert(VulkanObjectType::DrawImage, draw_image);
+        result?;
+        Ok(self.draw_image())
+    }
+
+    // Utilities
+
+    /// Logs the name, type, versions, IDs, and VRAM of every physical device reported by the driver.
+    pub fn log_physical_devices(&self) -> VkResult<()> {
+        let physical_devices = self.enumerate_physical_devices()?;
+        info!("Found {} physical device(s):", physical_devices.len());
+        for physical_device in physical_devices {
+            let properties = self.get_physical_device_properties(physical_device);
+            let memory_properties = self.get_physical_device_memory_properties(physical_device);
+            let name = properties.device_name_as_c_str().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            let vram = memory_properties.memory_heaps_as_slice()
+                .iter()
+                .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+                .map(|heap| heap.size)
+                .sum::<vk::DeviceSize>();
+            info!(
+                "    {name} ({:?}): Vulkan {}.{}.{}, driver version {:#x}, vendor ID {:#06x}, device ID {:#06x}, {} MiB VRAM",
+                properties.device_type,
+                vk::api_version_major(properties.api_version),
+                vk::api_version_minor(properties.api_version),
+                vk::api_version_patch(properties.api_version),
+                // Driver versions are encoded differently by each vendor, so leave them raw.
+                properties.driver_version,
+                properties.vendor_id,
+                properties.device_id,
+                vram / (1024 * 1024),
+            );
+        }
+        Ok(())
+    }
+
     // Inner Instance Methods
 
     #[inline]
@@ -311,12 +497,25 @@ impl Instance {
         unsafe { self.inner.get_physical_device_properties(physical_device) }
     }
 
+    #[inline]
+    pub fn get_physical_device_memory_properties(&self, physical_device: vk::PhysicalDevice) -> vk::PhysicalDeviceMemoryProperties {
+        // SAFETY: The object needs no additional allocation function.
+        unsafe { self.inner.get_physical_device_memory_properties(physical_device) }
+    }
+
     #[inline]
     pub fn get_physical_device_features(&self, physical_device: vk::PhysicalDevice) -> vk::PhysicalDeviceFeatures {
         // SAFETY: The object needs to additional allocation function.
         unsafe { self.inner.get_physical_device_features(physical_device) }
     }
 
+    /// Queries core and extended features via a caller-supplied `push_next` chain.
+    #[inline]
+    pub fn get_physical_device_features2(&self, physical_device: vk::PhysicalDevice, features: &mut vk::PhysicalDeviceFeatures2) {
+        // SAFETY: The object needs no additional allocation function.
+        unsafe { self.inner.get_physical_device_features2(physical_device, features) }
+    }
+
     #[inline]
     pub fn get_physical_device_queue_family_properties(&self, physical_device: vk::PhysicalDevice) -> Vec<vk::QueueFamilyProperties> {
         // SAFETY: The object needs no additional allocation function.
@@ -330,26 +529,29 @@ impl Instance {
     }
 
     // Helper Methods
+
+    /// Checks whether an instance extension is enabled in [`constants::ENABLED_EXTENSIONS`](crate::constants::ENABLED_EXTENSIONS).
+    pub fn is_extension_enabled(name: &CStr) -> bool {
+        crate::constants::ENABLED_EXTENSIONS.iter().any(|&ptr| {
+            // SAFETY: The extension names are guaranteed to be valid C strings.
+            let extension_name = unsafe { CStr::from_ptr(ptr) };
+            extension_name == name
+        })
+    }
     
-    /// # Parameter Guarantee
-    /// The `queue_flags` parameter is assumed to contain only one flag per element.
-    /// This is so that each flag can be indexed in the resulting [`HashMap`] via a single [`vk::QueueFlags`].
-    /// However, if you require multiple types of queues per queue family, you may add multiple flags to an element.
-    pub fn get_queue_family_map(&self, physical_device: vk::PhysicalDevice, queue_flags: &[vk::QueueFlags]) -> QueueFamilyMap {
-        let mut map = HashMap::new();
-        let queue_families = self.get_physical_device_queue_family_properties(physical_device);
-        for (queue_family_index, queue_family) in queue_families.iter().enumerate() {
-            let mut queue_index = 0; // the index within the queue family
-            for queue_flag in queue_flags.iter() {
-                if queue_family.queue_flags.contains(*queue_flag) && !map.contains_key(queue_flag) {
-                    map.insert(*queue_flag, (queue_family_index as u32, queue_index as u32));
-                    queue_index += 1; // increment the queue index once we've added one to the queue family
-                }
-            }
-        }
-        QueueFamilyMap {
-            inner: map,
-        }
+    /// Maps each of `queue_flags` to a queue of the physical device. See [`build_queue_family_map`].
+    pub fn get_queue_family_map(&self, physical_device: vk::PhysicalDevice, queue_flags: &[vk::QueueFlags], sharing: queues::QueueSharing) -> QueueFamilyMap {
+        build_queue_family_map(&self.get_physical_device_queue_family_properties(physical_device), queue_flags, sharing)
+    }
+
+    /// Returns every queue family supporting `queue_flags` alongside its queue count.
+    pub fn get_all_queue_families(&self, physical_device: vk::PhysicalDevice, queue_flags: vk::QueueFlags) -> Vec<(QueueFamilyIndex, u32)> {
+        all_queue_families(&self.get_physical_device_queue_family_properties(physical_device), queue_flags)
+    }
+
+    /// Selects a queue family of the physical device supporting `queue_flags`. See [`select_queue_family`].
+    pub fn select_queue_family(&self, physical_device: vk::PhysicalDevice, queue_flags: vk::QueueFlags) -> Option<QueueFamilyIndex> {
+        select_queue_family(&self.get_physical_device_queue_family_properties(physical_device), queue_flags)
     }
 }
 
@@ -393,11 +595,35 @@ pub struct Device {
     // use a ref-counter because the memory dependency is a little fucked.
     // basically, each VulkanObject allocated via an Allocator requires a reference to its Allocator for destruction.
     allocator: Rc<vk_mem::Allocator>,
+    /// Only available if `VK_EXT_debug_utils` is enabled.
+    debug_utils: Option<ext::debug_utils::Device>,
+}
+
+impl std::fmt::Debug for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Device")
+            .field("handle", &self.inner.handle())
+            .field("debug_utils", &self.debug_utils.is_some())
+            .finish_non_exhaustive()
+    }
 }
 
 impl Device {
     // Misc.
 
+    /// The raw device handle, for interop with libraries that use Vulkan directly.
+    #[inline]
+    pub fn handle(&self) -> &ash::Device {
+        &self.inner
+    }
+
+    /// Blocks until the device has finished all of its work.
+    #[inline]
+    pub fn wait_idle(&self) -> VkResult<()> {
+        // SAFETY: The object needs no additional allocation function.
+        unsafe { self.inner.device_wait_idle() }
+    }
+
     #[inline]
     pub fn get_device_queue(&self, queue_family_index: QueueFamilyIndex, queue_index: QueueIndex) -> vk::Queue {
         // SAFETY: The object needs no additional allocation function.
@@ -415,10 +641,55 @@ impl Device {
         unsafe { self.inner.queue_submit2(queue, submits, fence) }
     }
 
+    /// Blocks until all (or any, if `wait_all` is false) of the fences are signaled, or until `timeout` nanoseconds have passed.
+    #[inline]
+    pub fn wait_for_fences(&self, fences: &[vk::Fence], wait_all: bool, timeout: u64) -> VkResult<()> {
+        // SAFETY: The object needs no additional allocation function.
+        unsafe { self.inner.wait_for_fences(fences, wait_all, timeout) }
+    }
+
+    #[inline]
+    pub fn reset_fences(&self, fences: &[vk::Fence]) -> VkResult<()> {
+        // SAFETY: The object needs no additional allocation function.
+        unsafe { self.inner.reset_fences(fences) }
+    }
+
+    /// Records commands into a one-time command buffer, submits it, and blocks until it has executed.
+    /// This is meant for work outside of the frame loop, such as uploading buffers.
+    pub fn immediate_submit(&self, queue: &queues::Queue, record: impl FnOnce(vk::CommandBuffer)) -> VkResult<()> {
+        let command_pool_create_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(queue.queue_info().0);
+        let fence = self.create_fence(false)?;
+        // SAFETY: The command pool is destroyed before returning.
+        unsafe {
+            let command_pool = self.inner.create_command_pool(&command_pool_create_info, None)?;
+            let result = (|| {
+                let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::default()
+                    .command_pool(command_pool)
+                    .command_buffer_count(1)
+                    .level(vk::CommandBufferLevel::PRIMARY);
+                let command_buffer = self.inner.allocate_command_buffers(&command_buffer_allocate_info)?[0];
+                let begin_info = vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+                self.inner.begin_command_buffer(command_buffer, &begin_info)?;
+                record(command_buffer);
+                self.inner.end_command_buffer(command_buffer)?;
+
+                let command_buffer_submit_info = util::command_buffer_submit_info(command_buffer);
+                let submit_info = util::submit_info(&command_buffer_submit_info, &None, &None);
+                self.submit_queue(queue.handle(), &submit_info, *fence)?;
+                self.wait_for_fences(&[*fence], true, u64::MAX)
+            })();
+            self.inner.destroy_command_pool(command_pool, None);
+            result
+        }
+    }
+
     // Object Creation
 
     #[inline]
-    pub fn create_image(&self, create_info: &vk::ImageCreateInfo) -> VkResult<Image> {
+    pub fn create_image(&self, create_info: &vk::ImageCreateInfo) -> RenderResult<Image> {
         // SAFETY: The object is automatically destroyed.
         unsafe {
             let allocation_create_info = vk_mem::AllocationCreateInfo {
@@ -426,7 +697,8 @@ impl Device {
                 required_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
                 ..Default::default()
             };
-            let image = self.allocator.create_image(create_info, &allocation_create_info)?;
+            let image = self.allocator.create_image(create_info, &allocation_create_info)
+                .map_err(|result| allocation_error(result, &allocation_create_info))?;
             Ok(
                 VulkanObject::new(
                     image.0,
@@ -440,6 +712,100 @@ impl Device {
         }
     }
 
+    #[inline]
+    pub fn create_buffer(&self, create_info: &vk::BufferCreateInfo, allocation_create_info: &vk_mem::AllocationCreateInfo) -> RenderResult<Buffer> {
+        // SAFETY: The object is automatically destroyed.
+        unsafe {
+            let buffer = self.allocator.create_buffer(create_info, allocation_create_info)
+                .map_err(|result| allocation_error(result, allocation_create_info))?;
+            Ok(
+                VulkanObject::new(
+                    buffer.0,
+                    Some((self.allocator.clone(), buffer.1)),
+                    |buffer, data| {
+                        let (allocator, allocation) = data.as_mut().unwrap();
+                        allocator.destroy_buffer(*buffer, allocation);
+                    },
+                )
+            )
+        }
+    }
+
+    #[inline]
+    pub fn create_fence(&self, signaled: bool) -> VkResult<Fence> {
+        let flags = if signaled { vk::FenceCreateFlags::SIGNALED } else { vk::FenceCreateFlags::empty() };
+        let create_info = vk::FenceCreateInfo::default()
+            .flags(flags);
+        // SAFETY: The object is automatically destroyed.
+        unsafe {
+            Ok(
+                VulkanObject::new(
+                    self.inner.create_fence(&create_info, None)?,
+                    self.inner.clone(),
+                    |fence, device| device.destroy_fence(*fence, None),
+                )
+            )
+        }
+    }
+
+    #[inline]
+    pub fn create_semaphore(&self) -> VkResult<Semaphore> {
+        let create_info = vk::SemaphoreCreateInfo::default();
+        // SAFETY: The object is automatically destroyed.
+        unsafe {
+            Ok(
+                VulkanObject::new(
+                    self.inner.create_semaphore(&create_info, None)?,
+                    self.inner.clone(),
+                    |semaphore, device| device.destroy_semaphore(*semaphore, None),
+                )
+            )
+        }
+    }
+
+    #[inline]
+    pub fn create_sampler(&self, create_info: &vk::SamplerCreateInfo) -> VkResult<Sampler> {
+        // SAFETY: The object is automatically destroyed.
+        unsafe {
+            Ok(
+                VulkanObject::new(
+                    self.inner.create_sampler(create_info, None)?,
+                    self.inner.clone(),
+                    |sampler, device| device.destroy_sampler(*sampler, None),
+                )
+            )
+        }
+    }
+
+    #[inline]
+    pub fn create_pipeline_layout(&self, create_info: &vk::PipelineLayoutCreateInfo) -> VkResult<PipelineLayout> {
+        // SAFETY: The object is automatically destroyed.
+        unsafe {
+            Ok(
+                VulkanObject::new(
+                    self.inner.create_pipeline_layout(create_info, None)?,
+                    self.inner.clone(),
+                    |pipeline_layout, device| device.destroy_pipeline_layout(*pipeline_layout, None),
+                )
+            )
+        }
+    }
+
+    /// Creates a graphics pipeline through `pipeline_cache`, so that it is compiled from the cache if possible and persisted with it.
+    pub fn create_graphics_pipeline(&self, pipeline_cache: &pipeline::PipelineCache, create_info: &vk::GraphicsPipelineCreateInfo) -> VkResult<Pipeline> {
+        // SAFETY: The object is automatically destroyed. Nothing was created if this failed, since only one pipeline is created.
+        unsafe {
+            let pipelines = self.inner.create_graphics_pipelines(pipeline_cache.handle(), std::slice::from_ref(create_info), None).map_err(|(_, e)| e)?;
+            Ok(
+                VulkanObject::new(
+                    pipelines[0],
+                    self.inner.clone(),
+                    |pipeline, device| device.destroy_pipeline(*pipeline, None),
+                )
+            )
+        }
+    }
+
     #[inline]
     pub fn create_image_view(&self, create_info: &vk::ImageViewCreateInfo) -> VkResult<ImageView> {
         // SAFETY: The object is automatically destroyed.
@@ -455,6 +821,23 @@ impl Device {
     }
 }
 
+/// Describes allocation failures, which VMA otherwise reports as bare results.
+fn allocation_error(result: vk::Result, allocation_create_info: &vk_mem::AllocationCreateInfo) -> super::RenderError {
+    match result {
+        // VMA reports that no memory type matches the requirements as `VK_ERROR_FEATURE_NOT_PRESENT`.
+        // Running out of memory is reported as-is, since a suitable memory type exists but is full.
+        vk::Result::ERROR_FEATURE_NOT_PRESENT => super::RenderError::NoSuitableMemoryType {
+            result,
+            usage: allocation_create_info.usage,
+            required_flags: allocation_create_info.required_flags,
+        },
+        result => super::RenderError::Allocation {
+            result,
+            usage: allocation_create_info.usage,
+        },
+    }
+}
+
 impl Drop for Device {
     fn drop(&mut self) {
         // SAFETY: The object exists for the lifetime of this struct.
@@ -465,6 +848,7 @@ impl Drop for Device {
 }
 
 #[repr(transparent)]
+#[derive(Clone)]
 pub struct QueueFamilyMap {
     inner: HashMap<vk::QueueFlags, (QueueFamilyIndex, QueueIndex)>,
 }
@@ -484,3 +868,172 @@ impl std::fmt::Debug for QueueFamilyMap {
         f.debug_map().entries(&self.inner).finish()
     }
 }
+
+/// Maps each of `queue_flags` to a queue from `queue_families`, independently of any device.
+///
+/// # Parameter Guarantee
+/// The `queue_flags` parameter is assumed to contain only one flag per element.
+/// This is so that each flag can be indexed in the resulting [`HashMap`] via a single [`vk::QueueFlags`].
+/// However, if you require multiple types of queues per queue family, you may add multiple flags to an element.
+///
+/// See [`QueueSharing`](queues::QueueSharing) for how queue indices are assigned within a queue family.
+pub fn build_queue_family_map(queue_families: &[vk::QueueFamilyProperties], queue_flags: &[vk::QueueFlags], sharing: queues::QueueSharing) -> QueueFamilyMap {
+    let mut map = HashMap::new();
+    let mut queue_counts = HashMap::new(); // the number of queues taken from each queue family
+    for queue_flag in queue_flags.iter() {
+        if map.contains_key(queue_flag) {
+            continue
+        }
+        if let Some(queue_family_index) = select_queue_family(queue_families, *queue_flag) {
+            let queue_index = match sharing {
+                queues::QueueSharing::Distinct => {
+                    let taken = queue_counts.entry(queue_family_index).or_insert(0);
+                    let queue_index = *taken;
+                    *taken += 1; // increment the queue index once we've added one to the queue family
+                    // Requesting more queues than the family provides would fail device creation, so share the last one instead.
+                    let max_queue_index = queue_families[queue_family_index as usize].queue_count.saturating_sub(1);
+                    if queue_index > max_queue_index {
+                        info!("Queue family {queue_family_index} only has {} queue(s), so {queue_flag:?} shares queue {max_queue_index}", max_queue_index + 1);
+                    }
+                    queue_index.min(max_queue_index)
+                },
+                queues::QueueSharing::Shared => 0,
+            };
+            map.insert(*queue_flag, (queue_family_index, queue_index));
+        }
+    }
+    // Every flag must map to a queue that exists in a family supporting it, or device creation would fail.
+    debug_assert!(
+        map.iter().all(|(queue_flag, (queue_family_index, queue_index))| {
+            let queue_family = &queue_families[*queue_family_index as usize];
+            queue_family.queue_flags.contains(*queue_flag) && *queue_index < queue_family.queue_count
+        }),
+        "queue family map {map:?} assigns a queue that is out of range or lacks its flags",
+    );
+    QueueFamilyMap {
+        inner: map,
+    }
+}
+
+/// Returns every queue family in `queue_families` supporting `queue_flags` alongside its queue count.
+pub fn all_queue_families(queue_families: &[vk::QueueFamilyProperties], queue_flags: vk::QueueFlags) -> Vec<(QueueFamilyIndex, u32)> {
+    queue_families
+        .iter()
+        .enumerate()
+        .filter(|(_, queue_family)| queue_family.queue_flags.contains(queue_flags))
+        .map(|(queue_family_index, queue_family)| (queue_family_index as QueueFamilyIndex, queue_family.queue_count))
+        .collect()
+}
+
+/// Selects a queue family from `queue_families` supporting `queue_flags`.
+/// Graphics queues use the first matching family, whereas other queues (e.g. transfer and compute) prefer the most specialized family,
+/// since dedicated queue families usually map to separate hardware that can run alongside the graphics queue.
+pub fn select_queue_family(queue_families: &[vk::QueueFamilyProperties], queue_flags: vk::QueueFlags) -> Option<QueueFamilyIndex> {
+    let candidates = all_queue_families(queue_families, queue_flags);
+    if queue_flags.contains(vk::QueueFlags::GRAPHICS) {
+        return candidates.first().map(|(queue_family_index, _)| *queue_family_index)
+    }
+
+    candidates
+        .into_iter()
+        .min_by_key(|(queue_family_index, _)| {
+            let extra_flags = queue_families[*queue_family_index as usize].queue_flags & !queue_flags;
+            extra_flags.as_raw().count_ones()
+        })
+        .map(|(queue_family_index, _)| queue_family_index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_family(queue_flags: vk::QueueFlags, queue_count: u32) -> vk::QueueFamilyProperties {
+        vk::QueueFamilyProperties::default()
+            .queue_flags(queue_flags)
+            .queue_count(queue_count)
+    }
+
+    const ALL_FLAGS: &[vk::QueueFlags] = &[vk::QueueFlags::GRAPHICS, vk::QueueFlags::COMPUTE, vk::QueueFlags::TRANSFER];
+
+    #[test]
+    fn vulkan_objects_are_destroyed_once_on_drop() {
+        let destroyed = std::cell::Cell::new(0);
+        let object = VulkanObject::new(1u32, &destroyed, |_, destroyed| destroyed.set(destroyed.get() + 1));
+        assert_eq!(destroyed.get(), 0);
+        drop(object);
+        assert_eq!(destroyed.get(), 1);
+    }
+
+    #[test]
+    fn vulkan_objects_are_destroyed_in_order() {
+        // Swapchain image views are cleared in order before the swapchain handle is destroyed.
+        let destroyed = std::cell::RefCell::new(Vec::new());
+        let mut objects = (0..3).map(|i| VulkanObject::new(i, &destroyed, |i, destroyed| std::cell::RefCell::borrow_mut(destroyed).push(*i))).collect::<Vec<_>>();
+        objects.clear();
+        assert_eq!(*destroyed.borrow(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn combined_family_assigns_distinct_indices() {
+        let queue_families = [queue_family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 16)];
+        let map = build_queue_family_map(&queue_families, ALL_FLAGS, queues::QueueSharing::Distinct);
+        assert_eq!(map.get_queue_info(vk::QueueFlags::GRAPHICS), Some(&(0, 0)));
+        assert_eq!(map.get_queue_info(vk::QueueFlags::COMPUTE), Some(&(0, 1)));
+        assert_eq!(map.get_queue_info(vk::QueueFlags::TRANSFER), Some(&(0, 2)));
+    }
+
+    #[test]
+    fn combined_family_shares_last_queue_when_exhausted() {
+        let queue_families = [queue_family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 2)];
+        let map = build_queue_family_map(&queue_families, ALL_FLAGS, queues::QueueSharing::Distinct);
+        assert_eq!(map.get_queue_info(vk::QueueFlags::GRAPHICS), Some(&(0, 0)));
+        assert_eq!(map.get_queue_info(vk::QueueFlags::COMPUTE), Some(&(0, 1)));
+        assert_eq!(map.get_queue_info(vk::QueueFlags::TRANSFER), Some(&(0, 1)));
+    }
+
+    #[test]
+    fn combined_family_shared_uses_first_queue() {
+        let queue_families = [queue_family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 16)];
+        let map = build_queue_family_map(&queue_families, ALL_FLAGS, queues::QueueSharing::Shared);
+        for queue_flag in ALL_FLAGS {
+            assert_eq!(map.get_queue_info(*queue_flag), Some(&(0, 0)));
+        }
+    }
+
+    #[test]
+    fn separate_families_prefer_specialized_queues() {
+        let queue_families = [
+            queue_family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 1),
+            queue_family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 2),
+            queue_family(vk::QueueFlags::TRANSFER, 1),
+        ];
+        let map = build_queue_family_map(&queue_families, ALL_FLAGS, queues::QueueSharing::Distinct);
+        assert_eq!(map.get_queue_info(vk::QueueFlags::GRAPHICS), Some(&(0, 0)));
+        assert_eq!(map.get_queue_info(vk::QueueFlags::COMPUTE), Some(&(1, 0)));
+        assert_eq!(map.get_queue_info(vk::QueueFlags::TRANSFER), Some(&(2, 0)));
+    }
+
+    #[test]
+    fn repeated_and_unsupported_flags_are_skipped() {
+        let queue_families = [queue_family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::TRANSFER, 4)];
+        let queue_flags = [vk::QueueFlags::GRAPHICS, vk::QueueFlags::GRAPHICS, vk::QueueFlags::COMPUTE, vk::QueueFlags::TRANSFER];
+        let map = build_queue_family_map(&queue_families, &queue_flags, queues::QueueSharing::Distinct);
+        assert_eq!(map.inner().len(), 2);
+        assert_eq!(map.get_queue_info(vk::QueueFlags::GRAPHICS), Some(&(0, 0)));
+        assert_eq!(map.get_queue_info(vk::QueueFlags::COMPUTE), None);
+        assert_eq!(map.get_queue_info(vk::QueueFlags::TRANSFER), Some(&(0, 1)));
+    }
+
+    #[test]
+    fn allocation_errors_distinguish_missing_memory_types_from_exhaustion() {
+        let allocation_create_info = vk_mem::AllocationCreateInfo::default();
+        assert!(matches!(
+            allocation_error(vk::Result::ERROR_FEATURE_NOT_PRESENT, &allocation_create_info),
+            super::super::RenderError::NoSuitableMemoryType { result: vk::Result::ERROR_FEATURE_NOT_PRESENT, .. },
+        ));
+        assert!(matches!(
+            allocation_error(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY, &allocation_create_info),
+            super::super::RenderError::Allocation { result: vk::Result::ERROR_OUT_OF_DEVICE_MEMORY, .. },
+        ));
+    }
+}
@@ -1,4 +1,141 @@
 //! # Graphics Pipeline
 //! An interface with the graphics pipeline.
 
+use std::{fs, io, path::PathBuf};
+
+use ash::{prelude::VkResult, vk};
+
+use crate::{client::rendering::{RenderError, RenderResult}, warn};
+
 pub struct Pipeline {}
+
+impl Pipeline {
+    /// Ensures that a compute group size fits within the device's limits.
+    /// This must be checked upon creating a compute pipeline, since exceeding the limits is undefined behavior.
+    pub fn validate_compute_group_size(limits: &vk::PhysicalDeviceLimits, group_size: [u32; 3]) -> RenderResult<()> {
+        let exceeds_dimensions = group_size
+            .iter()
+            .zip(limits.max_compute_work_group_size.iter())
+            .any(|(size, max_size)| size > max_size);
+        let invocations = group_size.iter().product::<u32>();
+        if exceeds_dimensions || invocations > limits.max_compute_work_group_invocations {
+            return Err(RenderError::UnsupportedComputeGroupSize(group_size, limits.max_compute_work_group_size))
+        }
+
+        Ok(())
+    }
+}
+
+/// A cache of compiled pipelines, which is persisted to disk upon being dropped.
+pub struct PipelineCache {
+    handle: vk::PipelineCache,
+    device: ash::Device,
+    path: PathBuf,
+}
+
+impl PipelineCache {
+    /// Creates a pipeline cache, reloading its data from `path` if it is compatible with the device.
+    pub(super) fn new(device: ash::Device, properties: &vk::PhysicalDeviceProperties, path: PathBuf) -> VkResult<Self> {
+        let initial_data = match fs::read(&path) {
+            Ok(data) if is_compatible(&data, properties) => data,
+            Ok(_) => {
+                warn!("Discarding stale pipeline cache at {}", path.display());
+                Vec::new()
+            },
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Failed to read pipeline cache at {}: {e}", path.display());
+                }
+                Vec::new()
+            },
+        };
+        let create_info = vk::PipelineCacheCreateInfo::default()
+            .initial_data(&initial_data);
+        // SAFETY: The object is automatically dropped.
+        let handle = match unsafe { device.create_pipeline_cache(&create_info, None) } {
+            Ok(handle) => handle,
+            // The driver may still reject the data, in which case the cache is rebuilt from scratch.
+            Err(_) if !initial_data.is_empty() => {
+                warn!("Discarding pipeline cache rejected by the driver at {}", path.display());
+                // SAFETY: The object is automatically dropped.
+                unsafe { device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)? }
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(
+            Self {
+                handle,
+                device,
+                path,
+            }
+        )
+    }
+
+    #[inline]
+    pub fn handle(&self) -> vk::PipelineCache {
+        self.handle
+    }
+
+    /// Writes the cache's data to disk.
+    pub fn save(&self) -> RenderResult<()> {
+        // SAFETY: The device is available at this point.
+        let data = unsafe { self.device.get_pipeline_cache_data(self.handle)? };
+        fs::write(&self.path, data)?;
+        Ok(())
+    }
+}
+
+impl Drop for PipelineCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            warn!("Failed to save pipeline cache to {}: {e}", self.path.display());
+        }
+        // SAFETY: This is called upon dropping the pipeline cache.
+        unsafe { self.device.destroy_pipeline_cache(self.handle, None); }
+    }
+}
+
+/// Validates the pipeline cache header against the device, since caches are only valid for the device that created them.
+fn is_compatible(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    let read_u32 = |offset: usize| data.get(offset..offset + 4).map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()));
+    let header_version = read_u32(4);
+    let vendor_id = read_u32(8);
+    let device_id = read_u32(12);
+    let uuid = data.get(16..16 + vk::UUID_SIZE);
+
+    header_version == Some(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32)
+        && vendor_id == Some(properties.vendor_id)
+        && device_id == Some(properties.device_id)
+        && uuid == Some(properties.pipeline_cache_uuid.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_compute_work_group_size: [u32; 3], max_compute_work_group_invocations: u32) -> vk::PhysicalDeviceLimits {
+        vk::PhysicalDeviceLimits {
+            max_compute_work_group_size,
+            max_compute_work_group_invocations,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compute_group_size_within_limits_is_accepted() {
+        assert!(Pipeline::validate_compute_group_size(&limits([1024, 1024, 64], 1024), crate::constants::COMPUTE_GROUP_SIZE).is_ok());
+    }
+
+    #[test]
+    fn compute_group_size_exceeding_limits_is_rejected() {
+        assert!(matches!(
+            Pipeline::validate_compute_group_size(&limits([8, 1024, 64], 1024), [16, 16, 1]),
+            Err(RenderError::UnsupportedComputeGroupSize([16, 16, 1], [8, 1024, 64])),
+        ));
+        assert!(matches!(
+            Pipeline::validate_compute_group_size(&limits([1024, 1024, 64], 128), [16, 16, 1]),
+            Err(RenderError::UnsupportedComputeGroupSize(..)),
+        ));
+    }
+}
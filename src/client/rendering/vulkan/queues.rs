@@ -5,6 +5,8 @@ use std::collections::HashMap;
 
 use ash::{prelude::VkResult, vk};
 
+use crate::{client::rendering::{RenderError, RenderResult}, warn};
+
 const GRAPHICS: &'static str = "graphics queue should be available";
 
 #[derive(Debug)]
@@ -44,16 +46,27 @@ pub enum QueueType {
     PresentMode,
 }
 
+/// How queue indices are assigned to queue flags that map to the same queue family.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub enum QueueSharing {
+    /// Give each flag its own queue, sharing the family's last queue once they run out.
+    #[default]
+    Distinct,
+    /// Give every flag the family's first queue.
+    Shared,
+}
+
 #[derive(Debug)]
 pub struct QueueFamilies {
-    queues: HashMap<QueueType, Queue>,
+    /// The queues of each type, the first of which is the type's main queue.
+    queues: HashMap<QueueType, Vec<Queue>>,
     queue_priorities: HashMap<super::QueueFamilyIndex, Vec<f32>>,
 }
 
 impl QueueFamilies {
     pub fn new_empty(queue_family_map: &super::QueueFamilyMap) -> Self {
         let mut queues = HashMap::new();
-        queues.insert(QueueType::Graphics, Queue::new_empty(*queue_family_map.get_queue_info(vk::QueueFlags::GRAPHICS).expect(GRAPHICS), 1.0));
+        queues.insert(QueueType::Graphics, vec![Queue::new_empty(*queue_family_map.get_queue_info(vk::QueueFlags::GRAPHICS).expect(GRAPHICS), 1.0)]);
         Self {
             queues,
             queue_priorities: HashMap::new(),
@@ -61,24 +74,76 @@ impl QueueFamilies {
     }
 
     #[inline]
-    pub fn query_present_mode_queue(mut self, queue_family_map: &super::QueueFamilyMap, instance: &super::Instance, physical_device: vk::PhysicalDevice, surface: &super::Surface) -> VkResult<Self> {
+    pub fn query_present_mode_queue(mut self, queue_family_map: &super::QueueFamilyMap, instance: &super::Instance, physical_device: vk::PhysicalDevice, surface: &super::Surface) -> RenderResult<Self> {
         for (_, queue_info) in queue_family_map.inner().iter() {
             if instance.get_physical_device_surface_support(physical_device, queue_info.0, surface)? {
-                self.queues.insert(QueueType::PresentMode, Queue::new_empty(*queue_info, 1.0));
+                self.queues.insert(QueueType::PresentMode, vec![Queue::new_empty(*queue_info, 1.0)]);
+            }
+        }
+
+        // Fail early rather than panicking upon accessing the present queue.
+        if !self.queues.contains_key(&QueueType::PresentMode) {
+            return Err(RenderError::NoPresentQueue)
+        }
+
+        Ok(self)
+    }
+
+    /// Requests additional queues of a type from the queue family of its main queue (e.g. for recording on multiple threads),
+    /// each with the given priority.
+    /// The additional queues are limited by the family's queue count, so fewer may be requested than there are priorities.
+    pub fn request_additional_queues(mut self, queue_type: QueueType, priorities: &[f32], instance: &super::Instance, physical_device: vk::PhysicalDevice) -> RenderResult<Self> {
+        let queue_family_index = self.try_get_queue(queue_type).ok_or(RenderError::QueueUnavailable(queue_type))?.queue_info.0;
+        let queue_count = instance.get_physical_device_queue_family_properties(physical_device)[queue_family_index as usize].queue_count;
+        // Take the queues after every one already taken from the family.
+        let first_queue_index = self.queues
+            .values()
+            .flatten()
+            .filter(|queue| queue.queue_info.0 == queue_family_index)
+            .map(|queue| queue.queue_info.1 + 1)
+            .max()
+            .unwrap_or(0);
+        for (offset, priority) in priorities.iter().enumerate() {
+            let queue_index = first_queue_index + offset as u32;
+            if queue_index >= queue_count {
+                warn!("Queue family {queue_family_index} only has {queue_count} queue(s), so only {} additional {queue_type:?} queue(s) were requested", self.queue_count(queue_type) - 1);
+                break
             }
+            let queue = Queue::new_empty((queue_family_index, queue_index), *priority);
+            self.queues.get_mut(&queue_type).unwrap().push(queue);
         }
 
         Ok(self)
     }
 
     pub fn populate_handles(&mut self, device: &super::Device) {
-        self.queues.values_mut().for_each(|queue| queue.populate_handle(device));
+        self.queues.values_mut().flatten().for_each(|queue| queue.populate_handle(device));
+    }
+
+    /// Summarizes which queue family and index each queue type maps to, for diagnosing queue selection.
+    /// This is more focused than the [`Debug`] output.
+    pub fn summarize(&self) -> String {
+        let mut queue_types = self.queues.keys().copied().collect::<Vec<_>>();
+        queue_types.sort();
+        let mut summary = String::new();
+        for queue_type in queue_types {
+            for (n, queue) in self.queues[&queue_type].iter().enumerate() {
+                let (queue_family_index, queue_index) = queue.queue_info;
+                let populated = if queue.handle.is_some() { "populated" } else { "unpopulated" };
+                summary += &format!("\n  {queue_type:?}[{n}]: family {queue_family_index}, index {queue_index}, priority {}, {populated}", queue.priority);
+            }
+        }
+        summary
     }
 
-    pub fn get_queue_create_infos(&mut self, queue_family_map: &super::QueueFamilyMap) -> Vec<vk::DeviceQueueCreateInfo> {
+    /// # Errors
+    /// Returns [`RenderError::QueueIndexOutOfRange`] if a queue's index lies outside the queues requested from its family,
+    /// which happens if the queue was taken from a family without being listed in `queue_family_map`.
+    pub fn get_queue_create_infos(&mut self, queue_family_map: &super::QueueFamilyMap) -> RenderResult<Vec<vk::DeviceQueueCreateInfo>> {
         // Detect queue families and map them to their length.
         let mut family2len_map = HashMap::new();
-        for (_, (queue_family_index, queue_index)) in queue_family_map.inner().iter() {
+        let additional_queue_infos = self.queues.values().flat_map(|queues| queues.iter().skip(1)).map(Queue::queue_info);
+        for (queue_family_index, queue_index) in queue_family_map.inner().values().chain(additional_queue_infos) {
             if !family2len_map.contains_key(queue_family_index) || family2len_map.get(queue_family_index).unwrap() - 1 < *queue_index {
                 family2len_map.insert(*queue_family_index, queue_index + 1);
             }
@@ -88,9 +153,15 @@ impl QueueFamilies {
         let family2priority_map = &mut self.queue_priorities;
         for (queue_family_index, queue_family_length) in family2len_map {
             let mut priorities = vec![0.0f32; queue_family_length as usize];
-            for (_, queue) in self.queues.iter() {
+            for (queue_type, queue) in self.queues.iter().flat_map(|(queue_type, queues)| queues.iter().map(move |queue| (queue_type, queue))) {
                 if queue.queue_info.0 == queue_family_index {
-                    let _ = std::mem::replace(&mut priorities[queue.queue_info.1 as usize], queue.priority);
+                    let priority = priorities.get_mut(queue.queue_info.1 as usize).ok_or(RenderError::QueueIndexOutOfRange {
+                        queue_type: *queue_type,
+                        queue_family_index,
+                        queue_index: queue.queue_info.1,
+                        queue_count: queue_family_length,
+                    })?;
+                    *priority = queue.priority;
                 }
             }
             family2priority_map.insert(queue_family_index, priorities);
@@ -106,15 +177,48 @@ impl QueueFamilies {
             );
         }
 
-        create_infos
+        Ok(create_infos)
     }
 
+    /// Submits to the queue of the given type.
+    /// # Panics
+    /// Panics if the queue type wasn't requested at device creation.
     pub fn submit_queue<'a>(&self, device: &super::Device, queue_type: QueueType, submit: &'a vk::SubmitInfo2<'a>, fence: vk::Fence) -> VkResult<()> {
-        device.submit_queue(self.get_queue(queue_type).handle.expect("queue must be initialized before being submitted"), submit, fence)
+        device.submit_queue(self.get_queue(queue_type).handle(), submit, fence)
+    }
+
+    /// Submits to the queue of the given type, or returns an error if the queue type wasn't requested at device creation.
+    pub fn try_submit_queue<'a>(&self, device: &super::Device, queue_type: QueueType, submit: &'a vk::SubmitInfo2<'a>, fence: vk::Fence) -> RenderResult<()> {
+        let queue = self.try_get_queue(queue_type).ok_or(RenderError::QueueUnavailable(queue_type))?;
+        Ok(device.submit_queue(queue.handle(), submit, fence)?)
     }
 
     fn get_queue(&self, queue_type: QueueType) -> &Queue {
-        self.queues.get(&queue_type).unwrap()
+        self.try_get_queue(queue_type).unwrap_or_else(|| panic!("{queue_type:?} queue must be requested at device creation before being accessed"))
+    }
+
+    #[inline]
+    pub fn try_get_queue(&self, queue_type: QueueType) -> Option<&Queue> {
+        self.try_get_queue_n(queue_type, 0)
+    }
+
+    /// Returns the `n`th queue of the given type, where the 0th is the main queue and the rest were [requested additionally](Self::request_additional_queues).
+    #[inline]
+    pub fn try_get_queue_n(&self, queue_type: QueueType, n: usize) -> Option<&Queue> {
+        self.queues.get(&queue_type)?.get(n)
+    }
+
+    /// Returns the number of queues of the given type, including the main queue.
+    #[inline]
+    pub fn queue_count(&self, queue_type: QueueType) -> usize {
+        self.queues.get(&queue_type).map_or(0, Vec::len)
+    }
+
+    /// Returns the raw handle of the queue of the given type, e.g. for handing to a UI integration.
+    /// This is `None` if the queue type wasn't requested at device creation, or if [`Self::populate_handles`] hasn't been called yet.
+    #[inline]
+    pub fn queue_handle(&self, queue_type: QueueType) -> Option<vk::Queue> {
+        self.try_get_queue(queue_type)?.handle
     }
 
     #[inline]
@@ -122,6 +226,12 @@ impl QueueFamilies {
         self.get_queue(QueueType::Graphics)
     }
 
+    /// Returns the `n`th graphics queue, e.g. for submitting work recorded on another thread.
+    #[inline]
+    pub fn graphics_queue(&self, n: usize) -> Option<&Queue> {
+        self.try_get_queue_n(QueueType::Graphics, n)
+    }
+
     #[inline]
     pub fn present_mode(&self) -> &Queue {
         self.get_queue(QueueType::PresentMode)
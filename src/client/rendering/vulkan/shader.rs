@@ -1,12 +1,55 @@
 //! # Shader Abstractions
 //! Abstractions for opening and loading SPIR-V shaders.
 
-use std::{fs, path::PathBuf};
+use std::{collections::HashMap, fmt, fs, io::Cursor, path::PathBuf};
 
 use ash::{prelude::VkResult, vk};
 
 use crate::client::rendering::RenderResult;
 
+#[cfg(feature = "shader-reflection")]
+pub use reflection::ShaderReflection;
+
+/// The loaded shader modules, keyed by their source names (e.g. `triangle.vert`), so that loading a shader needn't reserve a [`VulkanObjectType`](super::VulkanObjectType).
+#[derive(Default)]
+pub struct ShaderRegistry {
+    modules: HashMap<String, ShaderModule>,
+}
+
+impl fmt::Debug for ShaderRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.modules.keys()).finish()
+    }
+}
+
+impl ShaderRegistry {
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&ShaderModule> {
+        self.modules.get(name)
+    }
+
+    #[inline]
+    pub fn contains(&self, name: &str) -> bool {
+        self.modules.contains_key(name)
+    }
+
+    /// Registers a shader module under `name`, replacing and destroying any module previously registered under it.
+    pub fn insert(&mut self, name: &str, shader_module: ShaderModule) -> &ShaderModule {
+        self.modules.insert(name.to_string(), shader_module);
+        &self.modules[name]
+    }
+
+    /// Unregisters the shader module, e.g. once the pipelines using it have been created.
+    #[inline]
+    pub fn remove(&mut self, name: &str) -> Option<ShaderModule> {
+        self.modules.remove(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.modules.keys().map(String::as_str)
+    }
+}
+
 pub struct ShaderModule {
     handle: vk::ShaderModule,
     device: ash::Device,
@@ -27,10 +70,107 @@ impl ShaderModule {
         )
     }
 
+    /// Creates a shader module from a SPIR-V file, keeping its bytecode for later use (e.g. reflection).
+    pub(super) fn from_spv_path(device: ash::Device, path: PathBuf) -> RenderResult<Self> {
+        let bytecode = fs::read(&path)?;
+        let code = ash::util::read_spv(&mut Cursor::new(&bytecode))?;
+        let create_info = vk::ShaderModuleCreateInfo::default()
+            .code(&code);
+        let mut shader_module = Self::new(device, &create_info, path)?;
+        shader_module.bytecode = Some(bytecode);
+        Ok(shader_module)
+    }
+
     pub fn read(&mut self) -> RenderResult<()> {
         self.bytecode = Some(fs::read(&self.path)?);
         Ok(())
     }
+
+    #[inline]
+    pub fn bytecode(&self) -> Option<&[u8]> {
+        self.bytecode.as_deref()
+    }
+
+    #[inline]
+    pub fn handle(&self) -> vk::ShaderModule {
+        self.handle
+    }
+
+    /// Creates the stage info for creating a pipeline with this shader's `main` entry point,
+    /// optionally setting its specialization constants (see [`Specialization::info`]).
+    pub fn stage_create_info<'a>(&self, stage: vk::ShaderStageFlags, specialization_info: Option<&'a vk::SpecializationInfo<'a>>) -> vk::PipelineShaderStageCreateInfo<'a> {
+        let mut create_info = vk::PipelineShaderStageCreateInfo::default()
+            .stage(stage)
+            .module(self.handle)
+            .name(c"main");
+        if let Some(specialization_info) = specialization_info {
+            create_info = create_info.specialization_info(specialization_info);
+        }
+
+        create_info
+    }
+
+    /// Reflects the descriptor bindings and push constant ranges declared by this shader.
+    /// The bytecode must have been kept or [read](Self::read) beforehand.
+    #[cfg(feature = "shader-reflection")]
+    pub fn reflect(&self) -> RenderResult<ShaderReflection> {
+        let bytecode = self.bytecode().ok_or_else(|| crate::client::rendering::RenderError::ShaderReflectionError(format!("the bytecode of {} has not been read", self.path.display())))?;
+        ShaderReflection::reflect(bytecode)
+    }
+}
+
+/// Values for a shader's specialization constants, which are baked into a pipeline upon creating it.
+/// This allows toggling shader behavior (e.g. workgroup sizes or feature flags) without recompiling the shader.
+#[derive(Clone, Default, Debug)]
+pub struct Specialization {
+    map_entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl Specialization {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the constant with the ID `constant_id` to the raw bytes of `value`, which must match the constant's type in the shader.
+    pub fn constant(mut self, constant_id: u32, value: &[u8]) -> Self {
+        debug_assert!(
+            self.map_entries.iter().all(|entry| entry.constant_id != constant_id),
+            "specialization constant {constant_id} was set more than once",
+        );
+        self.map_entries.push(
+            vk::SpecializationMapEntry::default()
+                .constant_id(constant_id)
+                .offset(self.data.len() as u32)
+                .size(value.len())
+        );
+        self.data.extend_from_slice(value);
+        self
+    }
+
+    #[inline]
+    pub fn u32(self, constant_id: u32, value: u32) -> Self {
+        self.constant(constant_id, &value.to_ne_bytes())
+    }
+
+    #[inline]
+    pub fn f32(self, constant_id: u32, value: f32) -> Self {
+        self.constant(constant_id, &value.to_ne_bytes())
+    }
+
+    /// Sets a boolean constant, which is a 32-bit [`vk::Bool32`] rather than a single byte.
+    #[inline]
+    pub fn bool(self, constant_id: u32, value: bool) -> Self {
+        self.u32(constant_id, if value { vk::TRUE } else { vk::FALSE })
+    }
+
+    /// Creates the specialization info referencing these constants, which must outlive pipeline creation.
+    pub fn info(&self) -> vk::SpecializationInfo<'_> {
+        vk::SpecializationInfo::default()
+            .map_entries(&self.map_entries)
+            .data(&self.data)
+    }
 }
 
 impl Drop for ShaderModule {
@@ -41,3 +181,401 @@ impl Drop for ShaderModule {
         }
     }
 }
+
+#[cfg(feature = "shader-reflection")]
+mod reflection {
+    use std::collections::HashMap;
+
+    use ash::vk;
+    use rspirv::{dr::{Instruction, Module, Operand}, spirv::{self, Word}};
+
+    use crate::client::rendering::{RenderError, RenderResult};
+
+    /// A descriptor binding declared by a shader.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct DescriptorBinding {
+        pub set: u32,
+        pub binding: u32,
+        pub descriptor_type: vk::DescriptorType,
+        /// The number of descriptors in the binding, or `0` for unbounded (runtime) arrays.
+        pub descriptor_count: u32,
+    }
+
+    /// The resource interface of a shader, as declared by its SPIR-V bytecode.
+    #[derive(Clone, Default, Debug)]
+    pub struct ShaderReflection {
+        pub stage: vk::ShaderStageFlags,
+        pub descriptor_bindings: Vec<DescriptorBinding>,
+        pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    }
+
+    impl ShaderReflection {
+        pub fn reflect(bytecode: &[u8]) -> RenderResult<Self> {
+            let module = rspirv::dr::load_bytes(bytecode).map_err(|e| RenderError::ShaderReflectionError(e.to_string()))?;
+            let reflector = Reflector::new(&module);
+
+            let stage = module.entry_points
+                .iter()
+                .filter_map(|entry_point| match entry_point.operands.first() {
+                    Some(Operand::ExecutionModel(execution_model)) => Some(stage_from_execution_model(*execution_model)),
+                    _ => None,
+                })
+                .fold(vk::ShaderStageFlags::empty(), |stages, stage| stages | stage);
+
+            let mut reflection = Self {
+                stage,
+                ..Default::default()
+            };
+            for variable in module.types_global_values.iter().filter(|instruction| instruction.class.opcode == spirv::Op::Variable) {
+                let (Some(variable_id), Some(pointer_type_id)) = (variable.result_id, variable.result_type) else {
+                    continue
+                };
+                let Some(Operand::StorageClass(storage_class)) = variable.operands.first() else {
+                    continue
+                };
+                let Some(pointee_type_id) = reflector.pointee(pointer_type_id) else {
+                    continue
+                };
+
+                match storage_class {
+                    spirv::StorageClass::PushConstant => {
+                        let (offset, size) = reflector.push_constant_range(pointee_type_id)?;
+                        reflection.push_constant_ranges.push(
+                            vk::PushConstantRange::default()
+                                .stage_flags(stage)
+                                .offset(offset)
+                                .size(size)
+                        );
+                    },
+                    spirv::StorageClass::UniformConstant | spirv::StorageClass::Uniform | spirv::StorageClass::StorageBuffer => {
+                        // Variables without a binding aren't part of the descriptor interface.
+                        let Some(binding) = reflector.decoration(variable_id, spirv::Decoration::Binding) else {
+                            continue
+                        };
+                        let set = reflector.decoration(variable_id, spirv::Decoration::DescriptorSet).unwrap_or(0);
+                        let (descriptor_type, descriptor_count) = reflector.descriptor_type(pointee_type_id, *storage_class)?;
+                        reflection.descriptor_bindings.push(DescriptorBinding {
+                            set,
+                            binding,
+                            descriptor_type,
+                            descriptor_count,
+                        });
+                    },
+                    _ => (),
+                }
+            }
+            reflection.descriptor_bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+            Ok(reflection)
+        }
+
+        /// Creates the layout bindings for a single descriptor set, visible to this shader's stage.
+        pub fn descriptor_set_layout_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+            self.descriptor_bindings
+                .iter()
+                .filter(|binding| binding.set == set)
+                .map(|binding| {
+                    vk::DescriptorSetLayoutBinding::default()
+                        .binding(binding.binding)
+                        .descriptor_type(binding.descriptor_type)
+                        .descriptor_count(binding.descriptor_count)
+                        .stage_flags(self.stage)
+                })
+                .collect()
+        }
+    }
+
+    /// Lookup tables over a parsed SPIR-V module.
+    struct Reflector<'a> {
+        types: HashMap<Word, &'a Instruction>,
+        constants: HashMap<Word, u32>,
+        annotations: &'a [Instruction],
+    }
+
+    impl<'a> Reflector<'a> {
+        fn new(module: &'a Module) -> Self {
+            let mut types = HashMap::new();
+            let mut constants = HashMap::new();
+            for instruction in module.types_global_values.iter() {
+                let Some(result_id) = instruction.result_id else {
+                    continue
+                };
+                match (instruction.class.opcode, instruction.operands.first()) {
+                    (spirv::Op::Constant, Some(Operand::LiteralInt32(value))) => {
+                        constants.insert(result_id, *value);
+                    },
+                    (spirv::Op::Variable, _) => (),
+                    _ => {
+                        types.insert(result_id, instruction);
+                    },
+                }
+            }
+
+            Self {
+                types,
+                constants,
+                annotations: &module.annotations,
+            }
+        }
+
+        /// Finds the literal of a decoration on an ID, or `Some(0)` if the decoration has no literal.
+        fn decoration(&self, target_id: Word, decoration: spirv::Decoration) -> Option<u32> {
+            self.annotations
+                .iter()
+                .filter(|instruction| instruction.class.opcode == spirv::Op::Decorate)
+                .find_map(|instruction| match instruction.operands.as_slice() {
+                    [Operand::IdRef(id), Operand::Decoration(found), rest @ ..] if *id == target_id && *found == decoration => {
+                        match rest.first() {
+                            Some(Operand::LiteralInt32(value)) => Some(*value),
+                            _ => Some(0),
+                        }
+                    },
+                    _ => None,
+                })
+        }
+
+        /// Finds the literal of a decoration on a struct member, or `Some(0)` if the decoration has no literal.
+        fn member_decoration(&self, struct_id: Word, member: u32, decoration: spirv::Decoration) -> Option<u32> {
+            self.annotations
+                .iter()
+                .filter(|instruction| instruction.class.opcode == spirv::Op::MemberDecorate)
+                .find_map(|instruction| match instruction.operands.as_slice() {
+                    [Operand::IdRef(id), Operand::LiteralInt32(index), Operand::Decoration(found), rest @ ..] if *id == struct_id && *index == member && *found == decoration => {
+                        match rest.first() {
+                            Some(Operand::LiteralInt32(value)) => Some(*value),
+                            _ => Some(0),
+                        }
+                    },
+                    _ => None,
+                })
+        }
+
+        fn type_instruction(&self, type_id: Word) -> RenderResult<&'a Instruction> {
+            self.types.get(&type_id).copied().ok_or_else(|| RenderError::ShaderReflectionError(format!("undefined type %{type_id}")))
+        }
+
+        fn id_operand(&self, instruction: &Instruction, index: usize) -> RenderResult<Word> {
+            match instruction.operands.get(index) {
+                Some(Operand::IdRef(id)) => Ok(*id),
+                _ => Err(RenderError::ShaderReflectionError(format!("expected an ID operand at {index} of {:?}", instruction.class.opcode))),
+            }
+        }
+
+        fn literal_operand(&self, instruction: &Instruction, index: usize) -> RenderResult<u32> {
+            match instruction.operands.get(index) {
+                Some(Operand::LiteralInt32(value)) => Ok(*value),
+                _ => Err(RenderError::ShaderReflectionError(format!("expected a literal operand at {index} of {:?}", instruction.class.opcode))),
+            }
+        }
+
+        fn array_length(&self, array: &Instruction) -> RenderResult<u32> {
+            let length_id = self.id_operand(array, 1)?;
+            self.constants.get(&length_id).copied().ok_or_else(|| RenderError::ShaderReflectionError(format!("array length %{length_id} is not a constant integer")))
+        }
+
+        fn pointee(&self, pointer_type_id: Word) -> Option<Word> {
+            let pointer = self.types.get(&pointer_type_id)?;
+            match pointer.operands.get(1) {
+                Some(Operand::IdRef(id)) if pointer.class.opcode == spirv::Op::TypePointer => Some(*id),
+                _ => None,
+            }
+        }
+
+        fn descriptor_type(&self, type_id: Word, storage_class: spirv::StorageClass) -> RenderResult<(vk::DescriptorType, u32)> {
+            // Unwrap arrays of descriptors.
+            let mut descriptor_count = 1;
+            let mut instruction = self.type_instruction(type_id)?;
+            loop {
+                match instruction.class.opcode {
+                    spirv::Op::TypeArray => descriptor_count *= self.array_length(instruction)?,
+                    spirv::Op::TypeRuntimeArray => descriptor_count = 0,
+                    _ => break,
+                }
+                instruction = self.type_instruction(self.id_operand(instruction, 0)?)?;
+            }
+
+            let descriptor_type = match instruction.class.opcode {
+                spirv::Op::TypeSampler => vk::DescriptorType::SAMPLER,
+                spirv::Op::TypeSampledImage => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                spirv::Op::TypeImage => {
+                    let Some(Operand::Dim(dim)) = instruction.operands.get(1) else {
+                        return Err(RenderError::ShaderReflectionError("image type is missing its dimensionality".to_string()))
+                    };
+                    // 1 = sampled, 2 = storage
+                    let storage = self.literal_operand(instruction, 5)? == 2;
+                    match (dim, storage) {
+                        (spirv::Dim::DimSubpassData, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                        (spirv::Dim::DimBuffer, false) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                        (spirv::Dim::DimBuffer, true) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                        (_, false) => vk::DescriptorType::SAMPLED_IMAGE,
+                        (_, true) => vk::DescriptorType::STORAGE_IMAGE,
+                    }
+                },
+                spirv::Op::TypeStruct => {
+                    let struct_id = instruction.result_id.unwrap_or_default();
+                    if storage_class == spirv::StorageClass::StorageBuffer || self.decoration(struct_id, spirv::Decoration::BufferBlock).is_some() {
+                        vk::DescriptorType::STORAGE_BUFFER
+                    } else {
+                        vk::DescriptorType::UNIFORM_BUFFER
+                    }
+                },
+                spirv::Op::TypeAccelerationStructureKHR => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                opcode => return Err(RenderError::ShaderReflectionError(format!("unsupported descriptor type {opcode:?}"))),
+            };
+
+            Ok((descriptor_type, descriptor_count))
+        }
+
+        /// Computes the offset and size of a push constant block from its member offsets.
+        fn push_constant_range(&self, struct_id: Word) -> RenderResult<(u32, u32)> {
+            let instruction = self.type_instruction(struct_id)?;
+            let mut start = u32::MAX;
+            let mut end = 0;
+            for member in 0..instruction.operands.len() as u32 {
+                let offset = self.member_decoration(struct_id, member, spirv::Decoration::Offset).unwrap_or(0);
+                start = start.min(offset);
+                end = end.max(offset + self.member_size(struct_id, member)?);
+            }
+
+            if start > end {
+                return Ok((0, 0))
+            }
+            Ok((start, end - start))
+        }
+
+        fn member_size(&self, struct_id: Word, member: u32) -> RenderResult<u32> {
+            let member_type_id = self.id_operand(self.type_instruction(struct_id)?, member as usize)?;
+            let member_type = self.type_instruction(member_type_id)?;
+            // Matrix strides are decorated on the member rather than the type.
+            if member_type.class.opcode == spirv::Op::TypeMatrix {
+                if let Some(matrix_stride) = self.member_decoration(struct_id, member, spirv::Decoration::MatrixStride) {
+                    let columns = self.literal_operand(member_type, 1)?;
+                    let vectors = if self.member_decoration(struct_id, member, spirv::Decoration::RowMajor).is_some() {
+                        self.literal_operand(self.type_instruction(self.id_operand(member_type, 0)?)?, 1)?
+                    } else {
+                        columns
+                    };
+                    return Ok(matrix_stride * vectors)
+                }
+            }
+
+            self.type_size(member_type_id)
+        }
+
+        fn type_size(&self, type_id: Word) -> RenderResult<u32> {
+            let instruction = self.type_instruction(type_id)?;
+            match instruction.class.opcode {
+                spirv::Op::TypeInt | spirv::Op::TypeFloat => Ok(self.literal_operand(instruction, 0)? / 8),
+                spirv::Op::TypeVector | spirv::Op::TypeMatrix => Ok(self.type_size(self.id_operand(instruction, 0)?)? * self.literal_operand(instruction, 1)?),
+                spirv::Op::TypeArray => {
+                    let length = self.array_length(instruction)?;
+                    match self.decoration(type_id, spirv::Decoration::ArrayStride) {
+                        Some(array_stride) => Ok(array_stride * length),
+                        None => Ok(self.type_size(self.id_operand(instruction, 0)?)? * length),
+                    }
+                },
+                spirv::Op::TypeStruct => {
+                    let mut size = 0;
+                    for member in 0..instruction.operands.len() as u32 {
+                        let offset = self.member_decoration(type_id, member, spirv::Decoration::Offset).unwrap_or(0);
+                        size = size.max(offset + self.member_size(type_id, member)?);
+                    }
+                    Ok(size)
+                },
+                opcode => Err(RenderError::ShaderReflectionError(format!("cannot compute the size of {opcode:?}"))),
+            }
+        }
+    }
+
+    fn stage_from_execution_model(execution_model: spirv::ExecutionModel) -> vk::ShaderStageFlags {
+        match execution_model {
+            spirv::ExecutionModel::Vertex => vk::ShaderStageFlags::VERTEX,
+            spirv::ExecutionModel::TessellationControl => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+            spirv::ExecutionModel::TessellationEvaluation => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+            spirv::ExecutionModel::Geometry => vk::ShaderStageFlags::GEOMETRY,
+            spirv::ExecutionModel::Fragment => vk::ShaderStageFlags::FRAGMENT,
+            spirv::ExecutionModel::GLCompute => vk::ShaderStageFlags::COMPUTE,
+            _ => vk::ShaderStageFlags::ALL,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use rspirv::{binary::Assemble, dr::Builder};
+
+        use super::*;
+
+        /// Builds a fragment shader with a uniform buffer at (1, 2), an array of 4 combined image samplers at (0, 0) and a `mat4` push constant.
+        fn fragment_shader() -> Vec<u8> {
+            let mut builder = Builder::new();
+            builder.set_version(1, 0);
+            builder.capability(spirv::Capability::Shader);
+            builder.memory_model(spirv::AddressingModel::Logical, spirv::MemoryModel::GLSL450);
+
+            let void = builder.type_void();
+            let function_type = builder.type_function(void, []);
+            let float = builder.type_float(32);
+            let uint = builder.type_int(32, 0);
+            let vec4 = builder.type_vector(float, 4);
+            let mat4 = builder.type_matrix(vec4, 4);
+
+            let uniform_block = builder.type_struct([vec4]);
+            builder.decorate(uniform_block, spirv::Decoration::Block, []);
+            builder.member_decorate(uniform_block, 0, spirv::Decoration::Offset, [Operand::LiteralInt32(0)]);
+            let uniform_pointer = builder.type_pointer(None, spirv::StorageClass::Uniform, uniform_block);
+            let uniform = builder.variable(uniform_pointer, None, spirv::StorageClass::Uniform, None);
+            builder.decorate(uniform, spirv::Decoration::DescriptorSet, [Operand::LiteralInt32(1)]);
+            builder.decorate(uniform, spirv::Decoration::Binding, [Operand::LiteralInt32(2)]);
+
+            let image = builder.type_image(float, spirv::Dim::Dim2D, 0, 0, 0, 1, spirv::ImageFormat::Unknown, None);
+            let sampled_image = builder.type_sampled_image(image);
+            let texture_count = builder.constant_u32(uint, 4);
+            let textures_type = builder.type_array(sampled_image, texture_count);
+            let textures_pointer = builder.type_pointer(None, spirv::StorageClass::UniformConstant, textures_type);
+            let textures = builder.variable(textures_pointer, None, spirv::StorageClass::UniformConstant, None);
+            builder.decorate(textures, spirv::Decoration::DescriptorSet, [Operand::LiteralInt32(0)]);
+            builder.decorate(textures, spirv::Decoration::Binding, [Operand::LiteralInt32(0)]);
+
+            let push_constant_block = builder.type_struct([mat4]);
+            builder.decorate(push_constant_block, spirv::Decoration::Block, []);
+            builder.member_decorate(push_constant_block, 0, spirv::Decoration::Offset, [Operand::LiteralInt32(0)]);
+            builder.member_decorate(push_constant_block, 0, spirv::Decoration::ColMajor, []);
+            builder.member_decorate(push_constant_block, 0, spirv::Decoration::MatrixStride, [Operand::LiteralInt32(16)]);
+            let push_constant_pointer = builder.type_pointer(None, spirv::StorageClass::PushConstant, push_constant_block);
+            builder.variable(push_constant_pointer, None, spirv::StorageClass::PushConstant, None);
+
+            let main = builder.begin_function(void, None, spirv::FunctionControl::NONE, function_type).unwrap();
+            builder.begin_block(None).unwrap();
+            builder.ret().unwrap();
+            builder.end_function().unwrap();
+            builder.entry_point(spirv::ExecutionModel::Fragment, main, "main", []);
+
+            builder.module()
+                .assemble()
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect()
+        }
+
+        #[test]
+        fn reflects_descriptor_bindings_and_push_constants() {
+            let reflection = ShaderReflection::reflect(&fragment_shader()).unwrap();
+            assert_eq!(reflection.stage, vk::ShaderStageFlags::FRAGMENT);
+            assert_eq!(reflection.descriptor_bindings, [
+                DescriptorBinding { set: 0, binding: 0, descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER, descriptor_count: 4 },
+                DescriptorBinding { set: 1, binding: 2, descriptor_type: vk::DescriptorType::UNIFORM_BUFFER, descriptor_count: 1 },
+            ]);
+            assert_eq!(reflection.push_constant_ranges.len(), 1);
+            assert_eq!((reflection.push_constant_ranges[0].offset, reflection.push_constant_ranges[0].size), (0, 64));
+
+            let bindings = reflection.descriptor_set_layout_bindings(1);
+            assert_eq!(bindings.len(), 1);
+            assert_eq!((bindings[0].binding, bindings[0].stage_flags), (2, vk::ShaderStageFlags::FRAGMENT));
+        }
+
+        #[test]
+        fn rejects_invalid_bytecode() {
+            assert!(matches!(ShaderReflection::reflect(&[0xde, 0xad, 0xbe, 0xef]), Err(RenderError::ShaderReflectionError(_))));
+        }
+    }
+}
@@ -1,29 +1,97 @@
 //! # Swapchain
 //! A collection of utilities for using swapchains.
 
+use std::{cell::RefCell, fmt};
+
 use ash::{khr, prelude::VkResult, vk};
 
-use crate::constants;
+use crate::{client::rendering::{RenderError, RenderResult}, constants, warn};
 
 pub struct Swapchain {
     handle: vk::SwapchainKHR,
     device: khr::swapchain::Device,
     images: Vec<super::Image>,
     image_view: Vec<super::ImageView>,
+    /// The semaphores signaled upon acquiring each image, indexed by image index.
+    acquire_semaphores: RefCell<Vec<super::Semaphore>>,
+    /// An unused semaphore for the next acquisition, since the image index isn't known until after acquiring.
+    spare_acquire_semaphore: RefCell<super::Semaphore>,
     format: vk::Format,
     extent: vk::Extent3D,
 }
 
+impl fmt::Debug for Swapchain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Swapchain")
+            .field("handle", &self.handle)
+            .field("images", &self.images)
+            .field("format", &self.format)
+            .field("extent", &self.extent)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Swapchain {
-    pub(super) fn new(handle: vk::SwapchainKHR, device: khr::swapchain::Device, images: Vec<super::Image>, image_view: Vec<super::ImageView>, format: vk::Format, extent: vk::Extent3D) -> Self {
-        Self {
+    pub(super) fn new(handle: vk::SwapchainKHR, device: khr::swapchain::Device, logical_device: &super::Device, images: Vec<super::Image>, image_view: Vec<super::ImageView>, format: vk::Format, extent: vk::Extent3D) -> VkResult<Self> {
+        // The images are destroyed alongside the swapchain handle, so they mustn't own an allocation that they'd free themselves.
+        debug_assert!(images.iter().all(|image| image.1.is_none()), "swapchain images must be undropped");
+        let acquire_semaphores = (0..images.len())
+            .map(|_| logical_device.create_semaphore())
+            .collect::<VkResult<Vec<_>>>()?;
+        let spare_acquire_semaphore = logical_device.create_semaphore()?;
+        Ok(
+            Self {
+                handle,
+                device,
+                images,
+                image_view,
+                acquire_semaphores: RefCell::new(acquire_semaphores),
+                spare_acquire_semaphore: RefCell::new(spare_acquire_semaphore),
+                format,
+                extent,
+            }
+        )
+    }
+
+    /// Creates a swapchain along with views of its images.
+    pub(super) fn create<'a>(device: &super::Device, swapchain_device: khr::swapchain::Device, create_info: &vk::SwapchainCreateInfoKHR, image_view_provider: impl FnOnce(&Vec<super::Image>, vk::Format) -> Vec<vk::ImageViewCreateInfo<'a>>) -> VkResult<Self> {
+        // SAFETY: The handle is destroyed below if the swapchain fails to take ownership of it.
+        let handle = unsafe { swapchain_device.create_swapchain(create_info, None)? };
+        let swapchain = Self::from_handle(handle, device, swapchain_device.clone(), create_info, image_view_provider);
+        if swapchain.is_err() {
+            // Any image views created so far have already been dropped, so the handle can be destroyed.
+            // SAFETY: Nothing else references the handle.
+            unsafe { swapchain_device.destroy_swapchain(handle, None) };
+        }
+        swapchain
+    }
+
+    fn from_handle<'a>(handle: vk::SwapchainKHR, device: &super::Device, swapchain_device: khr::swapchain::Device, create_info: &vk::SwapchainCreateInfoKHR, image_view_provider: impl FnOnce(&Vec<super::Image>, vk::Format) -> Vec<vk::ImageViewCreateInfo<'a>>) -> VkResult<Self> {
+        // SAFETY: The handle is valid.
+        let images = unsafe { swapchain_device.get_swapchain_images(handle)? }
+            .into_iter()
+            .map(|image| super::VulkanObject::undropped(image))
+            .collect::<Vec<_>>();
+        let image_view = image_view_provider(&images, create_info.image_format)
+            .into_iter()
+            .map(|create_info| device.create_image_view(&create_info))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(
             handle,
+            swapchain_device,
             device,
             images,
             image_view,
-            format,
-            extent,
-        }
+            create_info.image_format,
+            super::util::extent_2d_to_3d(create_info.image_extent),
+        )
+    }
+
+    /// Creates a new swapchain that takes over from this one via `oldSwapchain`, which avoids a visible hitch.
+    /// This swapchain is retired, but its handle is only destroyed once it is dropped, which must happen after the new swapchain is created.
+    pub fn recreate<'a>(&self, device: &super::Device, create_info: &vk::SwapchainCreateInfoKHR, image_view_provider: impl FnOnce(&Vec<super::Image>, vk::Format) -> Vec<vk::ImageViewCreateInfo<'a>>) -> VkResult<Self> {
+        let create_info = create_info.old_swapchain(self.handle);
+        Self::create(device, self.device.clone(), &create_info, image_view_provider)
     }
 
     #[inline]
@@ -37,11 +105,46 @@ impl Swapchain {
     }
 
     #[inline]
-    pub fn acquire_next_image(&self, frame: &super::commands::Frame) -> VkResult<u32> {
+    pub fn image_count(&self) -> usize {
+        self.images.len()
+    }
+
+    #[inline]
+    pub fn images(&self) -> &[super::Image] {
+        &self.images
+    }
+
+    /// The views of the swapchain images, indexed by image index.
+    #[inline]
+    pub fn image_views(&self) -> &[super::ImageView] {
+        &self.image_view
+    }
+
+    #[inline]
+    pub fn image_view(&self, image_index: u32) -> Option<&super::ImageView> {
+        self.image_view.get(image_index as usize)
+    }
+
+    #[inline]
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    /// Acquires the next image, whose acquire semaphore is then available via [`Self::acquire_semaphore`].
+    /// This retries if no image becomes available within `timeout` nanoseconds.
+    pub fn acquire_next_image(&self, timeout: u64) -> VkResult<(u32, bool)> {
+        let semaphore = **self.spare_acquire_semaphore.borrow();
         // SAFETY: The device is available at this point.
-        Ok(
-            unsafe { self.device.acquire_next_image(self.handle, constants::FENCE_TIMEOUT, frame.swapchain_semaphore(), vk::Fence::null())?.0 }
-        )
+        let (image_index, suboptimal) = super::util::retry_on_timeout("a swapchain image", || unsafe { self.device.acquire_next_image(self.handle, timeout, semaphore, vk::Fence::null()) })?;
+        // Recycle the image's previous semaphore, which is no longer in use now that the image has been re-acquired.
+        std::mem::swap(&mut self.acquire_semaphores.borrow_mut()[image_index as usize], &mut self.spare_acquire_semaphore.borrow_mut());
+        Ok((image_index, suboptimal))
+    }
+
+    /// The semaphore signaled once the image at `image_index` has been acquired.
+    #[inline]
+    pub fn acquire_semaphore(&self, image_index: u32) -> vk::Semaphore {
+        *self.acquire_semaphores.borrow()[image_index as usize]
     }
 
     #[inline]
@@ -58,11 +161,19 @@ impl Swapchain {
 
 impl Drop for Swapchain {
     fn drop(&mut self) {
+        // The image views reference the swapchain's images, which are owned by the swapchain handle,
+        // so they must be destroyed before the handle rather than after this destructor (i.e. in field order).
+        self.image_view.clear();
+        // The images themselves are undropped, since they are destroyed alongside the handle.
+        self.images.clear();
+
         // SAFETY: Vulkan functions are available at this time.
-        unsafe { self.device.destroy_swapchain(self.handle, None); }
+        unsafe { self.device.destroy_swapchain(self.handle, None) };
     }
 }
 
+/// The surface's capabilities as queried from the driver, which are plain data that can be cached.
+#[derive(Clone, Debug)]
 pub struct SwapchainSupport {
     capabilities: vk::SurfaceCapabilitiesKHR,
     formats: Vec<vk::SurfaceFormatKHR>,
@@ -81,6 +192,12 @@ impl SwapchainSupport {
         )
     }
 
+    /// Re-queries the surface capabilities, which change with the surface's size, keeping the supported formats and present modes.
+    pub fn refresh(&mut self, instance: &super::Instance, physical_device: vk::PhysicalDevice) -> VkResult<()> {
+        self.capabilities = instance.get_physical_device_surface_capabilities(physical_device, instance.surface())?;
+        Ok(())
+    }
+
     pub fn capabilities(&self) -> &vk::SurfaceCapabilitiesKHR {
         &self.capabilities
     }
@@ -93,30 +210,132 @@ impl SwapchainSupport {
         &self.present_modes
     }
 
-    pub fn select_format(&self) -> &vk::SurfaceFormatKHR {
-        for available_format in self.formats.iter() {
-            if available_format.format == vk::Format::B8G8R8A8_SRGB && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR {
-                return available_format
-            }
-        }
-
-        self.formats.get(0).unwrap()
+    /// Selects the first available format from `preferred_formats`, falling back to [`constants::SURFACE_FORMATS`] (SRGB)
+    /// and then to the first supported format. Pass an empty list to use the default preferences.
+    pub fn select_format(&self, preferred_formats: &[vk::SurfaceFormatKHR]) -> &vk::SurfaceFormatKHR {
+        select_surface_format(&self.formats, preferred_formats.iter().chain(constants::SURFACE_FORMATS))
+            .or(self.formats.first())
+            .expect("surfaces should support at least one format")
     }
 
-    pub fn select_present_mode(&self, preferred_mode: vk::PresentModeKHR) -> vk::PresentModeKHR {
-        for available_present_mode in self.present_modes.iter() {
-            if *available_present_mode == preferred_mode {
-                return preferred_mode
-            }
+    /// Selects the first available present mode from `preferred_modes`, falling back to FIFO.
+    /// A warning is logged if the most preferred mode is unavailable.
+    ///
+    /// # Errors
+    /// Returns [`RenderError::NoSupportedPresentMode`] if neither a preferred mode nor FIFO is supported.
+    /// FIFO is required by the spec, but broken drivers may report no present modes at all.
+    pub fn select_present_mode_ex(&self, preferred_modes: &[vk::PresentModeKHR]) -> RenderResult<vk::PresentModeKHR> {
+        let present_mode = preferred_modes
+            .iter()
+            .chain(std::iter::once(&vk::PresentModeKHR::FIFO))
+            .find(|preferred_mode| self.present_modes.contains(preferred_mode))
+            .copied()
+            .ok_or_else(|| RenderError::NoSupportedPresentMode { requested: preferred_modes.to_vec(), available: self.present_modes.clone() })?;
+        if let Some(requested_mode) = preferred_modes.first().filter(|requested_mode| **requested_mode != present_mode) {
+            warn!("Present mode {requested_mode:?} is unsupported, so falling back to {present_mode:?} (supported: {:?})", self.present_modes);
         }
 
-        vk::PresentModeKHR::FIFO
+        Ok(present_mode)
+    }
+
+    /// Selects the most preferred composite alpha mode supported by the surface, falling back to the first supported mode.
+    pub fn select_composite_alpha(&self) -> vk::CompositeAlphaFlagsKHR {
+        const PREFERRED_COMPOSITE_ALPHA: [vk::CompositeAlphaFlagsKHR; 4] = [
+            vk::CompositeAlphaFlagsKHR::OPAQUE,
+            vk::CompositeAlphaFlagsKHR::INHERIT,
+            vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+            vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+        ];
+        let supported_composite_alpha = self.capabilities.supported_composite_alpha;
+        PREFERRED_COMPOSITE_ALPHA
+            .into_iter()
+            .find(|composite_alpha| supported_composite_alpha.contains(*composite_alpha))
+            // Surfaces must support at least one mode, so fall back to the lowest set bit just in case the list above is missing one.
+            .unwrap_or(vk::CompositeAlphaFlagsKHR::from_raw(supported_composite_alpha.as_raw() & supported_composite_alpha.as_raw().wrapping_neg()))
     }
 
+    /// Selects the extent of the swapchain images.
+    /// The surface dictates the extent unless its current extent is `u32::MAX`, in which case the window size is clamped to the supported range.
     pub fn select_extent(&self, width: u32, height: u32) -> vk::Extent2D {
         let capabilities = self.capabilities();
+        if capabilities.current_extent.width != u32::MAX {
+            return capabilities.current_extent
+        }
         vk::Extent2D::default()
             .height(height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height))
             .width(width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width))
     }
 }
+
+/// Returns the first of `preferred_formats` that is in `available_formats`, independently of any device.
+pub fn select_surface_format<'a, 'b>(available_formats: &'a [vk::SurfaceFormatKHR], preferred_formats: impl IntoIterator<Item = &'b vk::SurfaceFormatKHR>) -> Option<&'a vk::SurfaceFormatKHR> {
+    preferred_formats
+        .into_iter()
+        .find_map(|preferred_format| available_formats.iter().find(|available_format| *available_format == preferred_format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swapchain_support(formats: &[vk::SurfaceFormatKHR], present_modes: &[vk::PresentModeKHR]) -> SwapchainSupport {
+        SwapchainSupport {
+            capabilities: vk::SurfaceCapabilitiesKHR::default(),
+            formats: formats.to_vec(),
+            present_modes: present_modes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn present_mode_preference_order_is_respected() {
+        let support = swapchain_support(&[], &[vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX]);
+        let present_mode = support.select_present_mode_ex(&[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE]).unwrap();
+        assert_eq!(present_mode, vk::PresentModeKHR::MAILBOX);
+        let present_mode = support.select_present_mode_ex(&[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::IMMEDIATE]).unwrap();
+        assert_eq!(present_mode, vk::PresentModeKHR::IMMEDIATE);
+    }
+
+    #[test]
+    fn present_mode_falls_back_to_fifo() {
+        let support = swapchain_support(&[], &[vk::PresentModeKHR::FIFO]);
+        let present_mode = support.select_present_mode_ex(&[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE]).unwrap();
+        assert_eq!(present_mode, vk::PresentModeKHR::FIFO);
+        assert_eq!(support.select_present_mode_ex(&[]).unwrap(), vk::PresentModeKHR::FIFO);
+    }
+
+    #[test]
+    fn present_mode_errors_without_present_modes() {
+        let support = swapchain_support(&[], &[]);
+        let error = support.select_present_mode_ex(&[vk::PresentModeKHR::MAILBOX]).unwrap_err();
+        assert!(matches!(
+            error,
+            RenderError::NoSupportedPresentMode { ref requested, ref available } if requested == &[vk::PresentModeKHR::MAILBOX] && available.is_empty()
+        ));
+    }
+
+    const SRGB: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR { format: vk::Format::B8G8R8A8_SRGB, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR };
+    const UNORM: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR { format: vk::Format::B8G8R8A8_UNORM, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR };
+
+    #[test]
+    fn format_prefers_hdr_when_present() {
+        let hdr = constants::HDR_SURFACE_FORMATS[0];
+        let support = swapchain_support(&[UNORM, SRGB, hdr], &[]);
+        assert_eq!(*support.select_format(constants::HDR_SURFACE_FORMATS), hdr);
+        assert_eq!(select_surface_format(&[UNORM, hdr], constants::HDR_SURFACE_FORMATS), Some(&hdr));
+    }
+
+    #[test]
+    fn format_falls_back_to_srgb() {
+        let support = swapchain_support(&[UNORM, SRGB], &[]);
+        assert_eq!(*support.select_format(constants::HDR_SURFACE_FORMATS), SRGB);
+        assert_eq!(*support.select_format(&[]), SRGB);
+    }
+
+    #[test]
+    fn format_falls_back_to_first_supported() {
+        let rgba = vk::SurfaceFormatKHR { format: vk::Format::R8G8B8A8_UNORM, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR };
+        let support = swapchain_support(&[rgba, UNORM], &[]);
+        assert_eq!(*support.select_format(constants::HDR_SURFACE_FORMATS), rgba);
+        assert_eq!(select_surface_format(&[rgba, UNORM], constants::SURFACE_FORMATS), None);
+    }
+}
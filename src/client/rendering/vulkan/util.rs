@@ -1,41 +1,123 @@
-use ash::vk;
+use ash::{prelude::VkResult, vk};
 
-use crate::constants;
+use crate::{constants, warn};
 
-/// metaphorically "memcpy"s an image to another image.
-/// i have nothing better to call this i promise.
-pub fn memcpy_image(frame: &super::commands::Frame, src: &super::Image, dst: &super::Image, src_size: vk::Extent3D, dst_size: vk::Extent3D, src_subresource: vk::ImageSubresourceLayers, dst_subresource: vk::ImageSubresourceLayers) {
+/// Calls `wait` until it stops timing out, giving up after [`constants::MAX_TIMEOUT_RETRIES`] retries.
+/// Timeouts are usually caused by a hitching GPU rather than a hang, so they aren't treated as errors right away.
+pub fn retry_on_timeout<T>(waiting_for: &str, mut wait: impl FnMut() -> VkResult<T>) -> VkResult<T> {
+    let mut retries = 0;
+    loop {
+        match wait() {
+            Err(vk::Result::TIMEOUT) if retries < constants::MAX_TIMEOUT_RETRIES => {
+                retries += 1;
+                warn!("Timed out waiting for {waiting_for}; retrying ({retries}/{})", constants::MAX_TIMEOUT_RETRIES);
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Plain data that may be viewed as bytes.
+///
+/// # Safety
+/// Every byte of the type must be initialized, i.e. it must have no padding, and it must not contain pointers or references.
+pub unsafe trait Pod: Copy {}
+
+// SAFETY: Primitive numbers have no padding.
+unsafe impl Pod for u8 {}
+// SAFETY: See above.
+unsafe impl Pod for u16 {}
+// SAFETY: See above.
+unsafe impl Pod for u32 {}
+// SAFETY: See above.
+unsafe impl Pod for f32 {}
+
+/// Views plain data as bytes, e.g. for uploading it to a buffer or push constants.
+#[inline]
+pub fn as_bytes<T: Pod>(data: &[T]) -> &[u8] {
+    // SAFETY: `T` is `Pod`, so all of its bytes are initialized.
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
+
+/// One side of a blit: the whole `size` of an image's subresource, starting from its origin.
+#[derive(Clone, Copy)]
+pub struct BlitRegion<'a> {
+    pub image: &'a super::Image,
+    pub size: vk::Extent3D,
+    pub subresource: vk::ImageSubresourceLayers,
+}
+
+/// Blits an image to another image, scaling it with `filter`.
+/// Depth and stencil blits must use [`vk::Filter::NEAREST`], and the aspects of both subresources must match.
+pub fn blit_image(frame: &super::commands::Frame, src: &BlitRegion, dst: &BlitRegion, filter: vk::Filter) {
+    debug_assert!(
+        filter == vk::Filter::NEAREST || !src.subresource.aspect_mask.intersects(vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL),
+        "depth and stencil blits must use nearest filtering",
+    );
     let blit_region = vk::ImageBlit2::default()
-        .src_offsets(
-            [
-                Default::default(),
-                vk::Offset3D::default()
-                    .x(src_size.width as i32)
-                    .y(src_size.height as i32)
-                    .z(src_size.depth as i32),
-            ],
-        )
-        .dst_offsets(
-            [
-                Default::default(),
-                vk::Offset3D::default()
-                    .x(dst_size.width as i32)
-                    .y(dst_size.height as i32)
-                    .z(dst_size.depth as i32),
-            ],
-        )
-        .src_subresource(src_subresource)
-        .dst_subresource(dst_subresource);
+        .src_offsets([Default::default(), extent_to_offset(src.size)])
+        .dst_offsets([Default::default(), extent_to_offset(dst.size)])
+        .src_subresource(src.subresource)
+        .dst_subresource(dst.subresource);
     let blit_info = vk::BlitImageInfo2::default()
-        .src_image(**src)
+        .src_image(**src.image)
         .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
-        .dst_image(**dst)
+        .dst_image(**dst.image)
         .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-        .filter(vk::Filter::LINEAR)
+        .filter(filter)
         .regions(std::slice::from_ref(&blit_region));
     frame.cmd_blit_image_2(&blit_info);
 }
 
+// Extents
+
+/// Converts a 2D extent into a 3D extent with a depth of 1.
+#[inline]
+pub fn extent_2d_to_3d(extent: vk::Extent2D) -> vk::Extent3D {
+    vk::Extent3D::default()
+        .width(extent.width)
+        .height(extent.height)
+        .depth(1)
+}
+
+/// Converts a 3D extent into a 2D extent, discarding its depth.
+#[inline]
+pub fn extent_3d_to_2d(extent: vk::Extent3D) -> vk::Extent2D {
+    vk::Extent2D::default()
+        .width(extent.width)
+        .height(extent.height)
+}
+
+/// Creates the offset of the far corner of an extent (e.g. for blit regions).
+#[inline]
+pub fn extent_to_offset(extent: vk::Extent3D) -> vk::Offset3D {
+    vk::Offset3D::default()
+        .x(extent.width as i32)
+        .y(extent.height as i32)
+        .z(extent.depth as i32)
+}
+
+/// Computes the number of mip levels in a full mip chain, down to 1x1.
+#[inline]
+pub fn mip_levels(extent: vk::Extent3D) -> u32 {
+    let largest_dimension = extent.width.max(extent.height).max(extent.depth).max(1);
+    u32::BITS - largest_dimension.leading_zeros()
+}
+
+// Compute
+
+/// Computes the number of workgroups needed to cover an extent, rounding up so that no texel is left out.
+/// Compute shaders must bounds-check against the extent themselves.
+#[inline]
+pub fn dispatch_groups(extent: vk::Extent3D, group_size: [u32; 3]) -> [u32; 3] {
+    debug_assert!(group_size.iter().all(|&size| size > 0), "compute group size must not be zero: {group_size:?}");
+    [
+        extent.width.div_ceil(group_size[0]),
+        extent.height.div_ceil(group_size[1]),
+        extent.depth.div_ceil(group_size[2]),
+    ]
+}
+
 // Info Structs
 
 #[inline]
@@ -100,26 +182,84 @@ pub fn submit_info_ex<'a>(command_buffer_submit_infos: &'a [vk::CommandBufferSub
         .command_buffer_infos(command_buffer_submit_infos)
 }
 
+/// Creates info for a color attachment in the `COLOR_ATTACHMENT_OPTIMAL` layout.
+/// The clear value is only used if `load_op` is [`vk::AttachmentLoadOp::CLEAR`].
+#[inline]
+pub fn color_attachment_info<'a>(image_view: &super::ImageView, clear: Option<vk::ClearValue>, load_op: vk::AttachmentLoadOp) -> vk::RenderingAttachmentInfo<'a> {
+    let mut attachment_info = vk::RenderingAttachmentInfo::default()
+        .image_view(**image_view)
+        .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .load_op(load_op)
+        .store_op(vk::AttachmentStoreOp::STORE);
+    if let Some(clear) = clear {
+        attachment_info = attachment_info.clear_value(clear);
+    }
+
+    attachment_info
+}
+
+/// Creates info for a repeating sampler using `filter` for magnification, minification, and mipmapping.
+/// Anisotropic filtering is used if `max_anisotropy` exceeds 1 and the `sampler_anisotropy` feature is enabled,
+/// in which case it is clamped to the device limit.
+pub fn sampler_create_info<'a>(filter: vk::Filter, max_anisotropy: f32, limits: &vk::PhysicalDeviceLimits, enabled_features: &super::features::DeviceFeatures) -> vk::SamplerCreateInfo<'a> {
+    let mipmap_mode = match filter {
+        vk::Filter::NEAREST => vk::SamplerMipmapMode::NEAREST,
+        _ => vk::SamplerMipmapMode::LINEAR,
+    };
+    let anisotropy_enable = max_anisotropy > 1.0 && enabled_features.core.sampler_anisotropy == vk::TRUE;
+    let mut create_info = vk::SamplerCreateInfo::default()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .mipmap_mode(mipmap_mode)
+        .address_mode_u(vk::SamplerAddressMode::REPEAT)
+        .address_mode_v(vk::SamplerAddressMode::REPEAT)
+        .address_mode_w(vk::SamplerAddressMode::REPEAT)
+        .max_lod(vk::LOD_CLAMP_NONE)
+        .anisotropy_enable(anisotropy_enable);
+    if anisotropy_enable {
+        create_info = create_info.max_anisotropy(max_anisotropy.min(limits.max_sampler_anisotropy));
+    }
+
+    create_info
+}
+
 #[inline]
 pub fn image_info_2d<'a>(format: vk::Format, extent: vk::Extent2D, image_usage_flags: vk::ImageUsageFlags) -> vk::ImageCreateInfo<'a> {
     image_info_ex(
         format,
-        extent.into(),
+        extent_2d_to_3d(extent),
         vk::ImageType::TYPE_2D,
         1,
+        1,
         constants::SAMPLES,
         image_usage_flags,
     )
 }
 
+/// Creates info for a cubemap, which has 6 square layers ordered +X, -X, +Y, -Y, +Z, -Z.
+/// View it with [`image_view_create_info_layered`] and [`vk::ImageViewType::CUBE`].
+#[inline]
+pub fn image_info_cube<'a>(format: vk::Format, size: u32, image_usage_flags: vk::ImageUsageFlags) -> vk::ImageCreateInfo<'a> {
+    image_info_ex(
+        format,
+        vk::Extent3D { width: size, height: size, depth: 1 },
+        vk::ImageType::TYPE_2D,
+        1,
+        6,
+        vk::SampleCountFlags::TYPE_1,
+        image_usage_flags,
+    )
+        .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+}
+
 #[inline]
-pub fn image_info_ex<'a>(format: vk::Format, extent: vk::Extent3D, image_type: vk::ImageType, mip_levels: u32, samples: vk::SampleCountFlags, image_usage_flags: vk::ImageUsageFlags) -> vk::ImageCreateInfo<'a> {
+pub fn image_info_ex<'a>(format: vk::Format, extent: vk::Extent3D, image_type: vk::ImageType, mip_levels: u32, array_layers: u32, samples: vk::SampleCountFlags, image_usage_flags: vk::ImageUsageFlags) -> vk::ImageCreateInfo<'a> {
     vk::ImageCreateInfo::default()
         .image_type(image_type)
         .format(format)
         .extent(extent)
         .mip_levels(mip_levels)
-        .array_layers(1)
+        .array_layers(array_layers)
         .samples(samples)
         .tiling(vk::ImageTiling::OPTIMAL) // always use the optimal format, for performance
         .usage(image_usage_flags)
@@ -135,6 +275,22 @@ pub fn image_view_create_info_2d<'a>(format: vk::Format, image: Option<&super::I
     )
 }
 
+/// Creates info for a view of the first `layer_count` layers of an image, e.g. [`vk::ImageViewType::CUBE`] with 6 layers
+/// or [`vk::ImageViewType::TYPE_2D_ARRAY`] for a texture array.
+#[inline]
+pub fn image_view_create_info_layered<'a>(image_view_type: vk::ImageViewType, format: vk::Format, image: Option<&super::Image>, image_aspect_flags: vk::ImageAspectFlags, layer_count: u32) -> vk::ImageViewCreateInfo<'a> {
+    debug_assert!(
+        !matches!(image_view_type, vk::ImageViewType::CUBE | vk::ImageViewType::CUBE_ARRAY) || layer_count.is_multiple_of(6),
+        "cube views must have a multiple of 6 layers, not {layer_count}",
+    );
+    image_view_create_info_ex(
+        image_view_type,
+        format,
+        image,
+        image_subresource_range(image_aspect_flags).layer_count(layer_count),
+    )
+}
+
 #[inline]
 pub fn image_view_create_info_ex<'a>(image_view_type: vk::ImageViewType, format: vk::Format, image: Option<&super::Image>, subresource_range: vk::ImageSubresourceRange) -> vk::ImageViewCreateInfo<'a> {
     let mut create_info = vk::ImageViewCreateInfo::default()
@@ -147,3 +303,22 @@ pub fn image_view_create_info_ex<'a>(image_view_type: vk::ImageViewType, format:
 
     create_info
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch_groups_round_up() {
+        let extent = vk::Extent3D { width: 1920, height: 1081, depth: 1 };
+        assert_eq!(dispatch_groups(extent, [16, 16, 1]), [120, 68, 1]);
+        let extent = vk::Extent3D { width: 1, height: 17, depth: 3 };
+        assert_eq!(dispatch_groups(extent, [16, 16, 2]), [1, 2, 2]);
+    }
+
+    #[test]
+    fn dispatch_groups_cover_empty_extents_with_no_groups() {
+        let extent = vk::Extent3D { width: 0, height: 0, depth: 0 };
+        assert_eq!(dispatch_groups(extent, [16, 16, 1]), [0, 0, 0]);
+    }
+}
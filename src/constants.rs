@@ -2,16 +2,26 @@ use std::{ffi::{c_char, CString}, sync::LazyLock, time::Duration};
 
 use ash::vk;
 
+use crate::client::rendering::vulkan::features::CoreFeature;
+
 // Info
 pub const NAME: &'static str = "SIGILL";
-pub const C_NAME: LazyLock<CString> = LazyLock::new(|| CString::new(NAME).unwrap());
+pub static C_NAME: LazyLock<CString> = LazyLock::new(|| CString::new(NAME).unwrap());
 pub const ISSUE_TRACKER: &'static str = "https://github.com/TerraxGames/SIGILL/issues";
 pub const VERSION: u32 = vk::make_api_version(0, 0, 1, 0);
 pub const ENGINE_VERSION: u32 = VERSION;
 /// The Vulkan API version.
 pub const API_VERSION: u32 = vk::API_VERSION_1_3;
-pub const API_VERSION_MAJOR: u32 = vk::api_version_major(API_VERSION);
-pub const API_VERSION_MINOR: u32 = vk::api_version_minor(API_VERSION);
+/// The path of the settings file, relative to the working directory.
+pub const SETTINGS_PATH: &'static str = "settings.toml";
+/// The name of the asset directory. See [`crate::assets`].
+pub const ASSET_DIR: &'static str = "assets";
+/// The directory of shaders relative to the asset root.
+pub const SHADER_ASSET_DIR: &'static str = "shader";
+/// The shaders loaded upon initializing the renderer, by source name.
+pub const PRELOADED_SHADERS: &'static [&'static str] = &["triangle.vert", "triangle.frag", "scene.vert", "scene.frag"];
+/// The path of the pipeline cache, relative to the working directory.
+pub const PIPELINE_CACHE_PATH: &'static str = "pipeline_cache.bin";
 
 // Rendering
 pub const REQUIRED_VALIDATION_LAYERS: &'static [*const c_char] = &[
@@ -19,11 +29,11 @@ pub const REQUIRED_VALIDATION_LAYERS: &'static [*const c_char] = &[
     c"VK_LAYER_KHRONOS_validation".as_ptr()
 ];
 pub const ENABLE_VALIDATION_LAYERS: bool = cfg!(debug_assertions);
-pub const REQUIRED_QUEUE_FAMILIES: LazyLock<vk::QueueFlags> = LazyLock::new(|| vk::QueueFlags::GRAPHICS);
-pub const ENABLED_DEVICE_FEATURES: LazyLock<vk::PhysicalDeviceFeatures> = LazyLock::new(||
-    vk::PhysicalDeviceFeatures::default()
-        .geometry_shader(true)
-);
+/// The core device features that devices must support, which are enabled at device creation.
+pub const REQUIRED_CORE_FEATURES: &'static [CoreFeature] = &[
+    CoreFeature::GeometryShader,
+];
+pub static ENABLED_DEVICE_FEATURES: LazyLock<vk::PhysicalDeviceFeatures> = LazyLock::new(|| CoreFeature::features(REQUIRED_CORE_FEATURES));
 pub const ENABLED_EXTENSIONS: &'static [*const c_char] = &[
     ash::ext::debug_utils::NAME.as_ptr(),
 ];
@@ -31,17 +41,59 @@ pub const ENABLED_DEVICE_EXTENSIONS: &'static [*const c_char] = &[
     ash::khr::swapchain::NAME.as_ptr(),
 ];
 /// A list of queue families used at runtime.
-pub const QUEUE_FAMILIES: LazyLock<&'static [vk::QueueFlags]> = LazyLock::new(||
+/// This is the single source of truth for queue families; add new queue families here.
+pub static QUEUE_FAMILIES: LazyLock<&'static [vk::QueueFlags]> = LazyLock::new(||
     &[
         vk::QueueFlags::GRAPHICS,
     ]
 );
+/// The priorities of the graphics queues to request in addition to the main one, e.g. for recording on multiple threads.
+pub const ADDITIONAL_GRAPHICS_QUEUE_PRIORITIES: &'static [f32] = &[];
+/// The queue capabilities that devices must support, derived from [`QUEUE_FAMILIES`] so that the two never disagree.
+pub static REQUIRED_QUEUE_FAMILIES: LazyLock<vk::QueueFlags> = LazyLock::new(||
+    QUEUE_FAMILIES.iter().fold(vk::QueueFlags::empty(), |required, queue_flags| required | *queue_flags)
+);
+/// The default surface formats in order of preference.
+pub const SURFACE_FORMATS: &'static [vk::SurfaceFormatKHR] = &[
+    vk::SurfaceFormatKHR { format: vk::Format::B8G8R8A8_SRGB, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR },
+];
+/// The surface formats used for HDR output in order of preference, which require `VK_EXT_swapchain_colorspace`.
+pub const HDR_SURFACE_FORMATS: &'static [vk::SurfaceFormatKHR] = &[
+    vk::SurfaceFormatKHR { format: vk::Format::A2B10G10R10_UNORM_PACK32, color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT },
+    vk::SurfaceFormatKHR { format: vk::Format::R16G16B16A16_SFLOAT, color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT },
+];
 pub const FRAMEBUFFER_SIZE: usize = 2;
+/// The bounds of the render scale, beyond which the image is either unrecognizable or too costly to render.
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+/// The size of each of a [`StagingPool`](crate::client::rendering::vulkan::buffer::StagingPool)'s buffers by default.
+pub const STAGING_BUFFER_SIZE: u64 = 16 * 1024 * 1024;
+/// The total size of a [`StagingPool`](crate::client::rendering::vulkan::buffer::StagingPool)'s buffers by default.
+pub const STAGING_POOL_BUDGET: u64 = 4 * STAGING_BUFFER_SIZE;
+/// How long the window size must remain unchanged before the swapchain is recreated, so that dragging a window edge doesn't rebuild it every frame.
+pub const RESIZE_DEBOUNCE: Duration = Duration::from_millis(100);
+/// The key that toggles wireframe rendering, for debugging geometry.
+pub const WIREFRAME_TOGGLE_KEY: winit::keyboard::KeyCode = winit::keyboard::KeyCode::F3;
+/// The default timeout of fence waits and image acquisitions, in nanoseconds.
 pub const FENCE_TIMEOUT: u64 = Duration::from_secs(1).as_nanos() as u64;
+/// The number of times a timed-out wait is retried before it is treated as an error.
+pub const MAX_TIMEOUT_RETRIES: u32 = 3;
 pub const MIP_LEVEL: u32 = 0;
 pub const SAMPLES: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_1;
+/// The local workgroup size of compute shaders, which must match the `local_size_*` layout qualifiers in GLSL.
+pub const COMPUTE_GROUP_SIZE: [u32; 3] = [16, 16, 1];
+
+// Game Logic
+/// The default number of game ticks per second.
+pub const TICK_RATE: u32 = 60;
+/// The maximum number of ticks to catch up on per update, so that a long stall doesn't freeze the game.
+pub const MAX_TICKS_PER_UPDATE: u32 = 8;
 
 // Logging
+/// The path of the log file, relative to the working directory.
+pub const LOG_PATH: &'static str = "latest.log";
+/// How often average CPU frame timings are logged.
+pub const FRAME_METRICS_INTERVAL: Duration = Duration::from_secs(5);
 pub const LOG_LEVEL: log::LevelFilter = {
     if cfg!(debug_assertions) {
         log::LevelFilter::Trace
@@ -25,12 +25,12 @@ macro_rules! client_only {
 
 #[macro_export]
 macro_rules! dedicated_server_only {
-    ( $side:expr, $code:expr ) => {
+    ( $side:expr, $code:block ) => {
         $crate::sided!($crate::environment::Side::DedicatedServer, $side, $code)
     };
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Side {
     Client,
     DedicatedServer,
@@ -44,3 +44,19 @@ impl core::fmt::Display for Side {
         }
     }
 }
+
+#[derive(thiserror::Error, Debug)]
+#[error("unknown side `{0}` (expected client, server, or dedicated_server)")]
+pub struct ParseSideError(String);
+
+impl core::str::FromStr for Side {
+    type Err = ParseSideError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "client" => Ok(Self::Client),
+            "server" | "dedicated_server" => Ok(Self::DedicatedServer),
+            _ => Err(ParseSideError(s.to_string())),
+        }
+    }
+}
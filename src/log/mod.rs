@@ -1,20 +1,37 @@
 use core::fmt;
+use std::{fs::File, io::{BufWriter, IsTerminal, Write}, sync::{atomic::{AtomicBool, Ordering}, Mutex}};
 
 use colored::{ColoredString, Colorize};
-use log::error;
+use log::{error, Log};
 
 use crate::constants;
 
-static LOGGER: Logger = Logger;
+static LOGGER: Logger = Logger::new();
 
-pub fn init() -> Result<(), log::SetLoggerError> {
+/// Initializes logging to stdout and the log file.
+/// The returned guard must be held for the program's lifetime, since it flushes the log file upon being dropped.
+pub fn init(level: log::LevelFilter) -> Result<LogGuard, log::SetLoggerError> {
     log::set_logger(&LOGGER)
-        .map(|()| log::set_max_level(constants::LOG_LEVEL))
+        .map(|()| log::set_max_level(level))?;
+    // Only colorize when writing to a terminal, since escape codes garble redirected output.
+    // See https://no-color.org/
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|no_color| !no_color.is_empty());
+    LOGGER.colorize_stdout.store(std::io::stdout().is_terminal() && !no_color, Ordering::Relaxed);
+    LOGGER.colorize_stderr.store(std::io::stderr().is_terminal() && !no_color, Ordering::Relaxed);
+    match File::create(constants::LOG_PATH) {
+        Ok(file) => *LOGGER.file.lock().unwrap() = Some(BufWriter::new(file)),
+        Err(e) => error!("Failed to create log file {}: {e}", constants::LOG_PATH),
+    }
+    Ok(LogGuard)
 }
 
 pub fn hook_panic() {
     std::panic::set_hook(Box::new(|panic_info| {
-        if let Some(payload) = panic_info.payload().downcast_ref::<String>() {
+        LOGGER.flush();
+        // Panics carry a `&'static str` payload if their message is a literal, and a `String` if it is formatted.
+        let payload = panic_info.payload();
+        let payload = payload.downcast_ref::<String>().map(String::as_str).or_else(|| payload.downcast_ref::<&str>().copied());
+        if let Some(payload) = payload {
             error!(r#"{} has encountered a fatal error and cannot recover!
 {}
 {payload}
@@ -22,15 +39,42 @@ Please report this bug on our issue tracker: {}"#, constants::NAME, panic_info.l
         } else {
             error!("{} has encountered a fatal error and cannot recover!\nPlease report this bug on our issue tracker: {}", constants::NAME, constants::ISSUE_TRACKER);
         }
+        LOGGER.flush();
     }));
 }
 
-// TODO: implement log files
-pub struct Logger;
+/// Flushes and closes the log file upon being dropped.
+pub struct LogGuard;
+
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        if let Ok(mut file) = LOGGER.file.lock() {
+            if let Some(mut file) = file.take() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+pub struct Logger {
+    file: Mutex<Option<BufWriter<File>>>,
+    colorize_stdout: AtomicBool,
+    colorize_stderr: AtomicBool,
+}
+
+impl Logger {
+    const fn new() -> Self {
+        Self {
+            file: Mutex::new(None),
+            colorize_stdout: AtomicBool::new(true),
+            colorize_stderr: AtomicBool::new(true),
+        }
+    }
+}
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() <= constants::LOG_LEVEL
+        metadata.level() <= log::max_level()
     }
 
     fn log(&self, record: &log::Record) {
@@ -56,11 +100,30 @@ impl log::Log for Logger {
                     target
                 }
             };
-            println!("{origin}{}   {}", format_level(record.level()), colorize_args(record.level(), record.args()));
+            // Route errors and warnings to stderr so that they aren't swallowed when piping stdout.
+            if record.level() <= log::Level::Warn {
+                let colorize = self.colorize_stderr.load(Ordering::Relaxed);
+                eprintln!("{origin}{}   {}", format_level(record.level(), colorize), colorize_args(record.level(), record.args(), colorize));
+            } else {
+                let colorize = self.colorize_stdout.load(Ordering::Relaxed);
+                println!("{origin}{}   {}", format_level(record.level(), colorize), colorize_args(record.level(), record.args(), colorize));
+            }
+            // Don't panic while logging, since a poisoned lock means a panic is already underway.
+            if let Ok(mut file) = self.file.lock() {
+                if let Some(file) = file.as_mut() {
+                    let _ = writeln!(file, "{origin}{}   {}", record.level(), record.args());
+                }
+            }
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(file) = file.as_mut() {
+                let _ = file.flush();
+            }
+        }
+    }
 }
 
 fn level_color(level: log::Level) -> colored::Color {
@@ -73,10 +136,18 @@ fn level_color(level: log::Level) -> colored::Color {
     }
 }
 
-fn format_level(level: log::Level) -> ColoredString {
-    level.as_str().color(level_color(level))
+fn format_level(level: log::Level, colorize: bool) -> ColoredString {
+    if colorize {
+        level.as_str().color(level_color(level))
+    } else {
+        level.as_str().into()
+    }
 }
 
-fn colorize_args(level: log::Level, args: &fmt::Arguments) -> ColoredString {
-    args.to_string().color(level_color(level))
+fn colorize_args(level: log::Level, args: &fmt::Arguments, colorize: bool) -> ColoredString {
+    if colorize {
+        args.to_string().color(level_color(level))
+    } else {
+        args.to_string().into()
+    }
 }
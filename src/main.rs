@@ -1,36 +1,59 @@
-use client::{rendering::RenderData, ClientData};
+use client::{rendering::{AppInfo, RenderData}, ClientData, FatalErrorPhase};
 use environment::Side;
+use std::time::{Duration, Instant};
+
 use hecs::World;
-use winit::{event::WindowEvent, event_loop::{ControlFlow, EventLoop}, window::{Window, WindowAttributes}};
+use scheduler::Scheduler;
+use settings::{ControlFlowStrategy, Settings};
+use winit::{event::WindowEvent, event_loop::{ControlFlow, EventLoop}, keyboard::PhysicalKey, window::{Window, WindowAttributes}};
 
 pub use ::log::{error, warn, info, debug, trace}; // easy logging anywhere
 
 mod log;
+mod assets;
 mod constants;
 mod event;
 mod environment;
 mod client;
 mod util;
+mod settings;
+mod scheduler;
 
 struct App {
     side: Side,
+    settings: Settings,
     client_data: Option<ClientData>,
     world: World,
+    scheduler: Scheduler,
+    /// The fixed duration of a game tick, computed once from the tick rate.
+    tick_duration: Duration,
+    last_tick: Instant,
+    tick_accumulator: Duration,
 }
 
 impl App {
-    pub fn new_client(attributes: winit::window::WindowAttributes) -> Self {
+    pub fn new_client(settings: Settings, attributes: winit::window::WindowAttributes, app_info: AppInfo) -> Self {
         Self::new(
             Side::Client,
-            Some(ClientData { window: None, attributes, render_data: None })
+            settings,
+            Some(ClientData { window: None, attributes, render_data: None, app_info, pending_resize: None, failed: false })
         )
     }
 
-    pub fn new(side: Side, client_data: Option<ClientData>) -> Self {
+    pub fn new_dedicated_server(settings: Settings) -> Self {
+        Self::new(Side::DedicatedServer, settings, None)
+    }
+
+    pub fn new(side: Side, settings: Settings, client_data: Option<ClientData>) -> Self {
         Self {
             side,
+            tick_duration: settings.tick_duration(),
+            settings,
             client_data,
             world: World::new(),
+            scheduler: Scheduler::new(),
+            last_tick: Instant::now(),
+            tick_accumulator: Duration::ZERO,
         }
     }
 
@@ -56,6 +79,16 @@ impl App {
         })
     }
 
+    /// Returns the window if this is the client and it has been created, without panicking.
+    pub fn try_window(&self) -> Option<&Window> {
+        self.client_data()?.window.as_ref()
+    }
+
+    /// Returns the rendering data if this is the client and rendering has been initialized, without panicking.
+    pub fn try_render_data(&self) -> Option<&RenderData> {
+        self.client_data()?.render_data.as_ref()
+    }
+
     pub fn render_data(&self) -> &RenderData {
         client_only!(self.side, {
             self.client_data().unwrap().render_data.as_ref().expect("rendering should be initialized before accessing rendering data")
@@ -71,15 +104,124 @@ impl App {
     pub fn side(&self) -> Side {
         self.side
     }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    pub fn scheduler_mut(&mut self) -> &mut Scheduler {
+        &mut self.scheduler
+    }
+
+    #[inline]
+    pub fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    /// How far the current time is between the last tick and the next, from 0 to 1.
+    /// Rendering should interpolate between the last two ticks' states by this much so that motion is smooth at any frame rate.
+    pub fn interpolation_alpha(&self) -> f32 {
+        (self.tick_accumulator.as_secs_f64() / self.tick_duration.as_secs_f64()).clamp(0.0, 1.0) as f32
+    }
+
+    /// Runs this side's systems at a fixed timestep, catching up on any ticks that have elapsed since the last update.
+    /// Returns the number of ticks that were run.
+    pub fn tick(&mut self) -> u32 {
+        let now = Instant::now();
+        self.tick_accumulator += now - self.last_tick;
+        self.last_tick = now;
+
+        let mut ticks = 0;
+        while self.tick_accumulator >= self.tick_duration {
+            if ticks >= constants::MAX_TICKS_PER_UPDATE {
+                // Drop the remaining ticks rather than spiraling further behind.
+                self.tick_accumulator = Duration::ZERO;
+                break
+            }
+            self.scheduler.run(self.side, &mut self.world, self.tick_duration);
+            self.tick_accumulator -= self.tick_duration;
+            ticks += 1;
+        }
+
+        ticks
+    }
+
+    /// Shows a fatal error to the user and exits the event loop, marking the client as failed.
+    fn fail(&mut self, event_loop: &winit::event_loop::ActiveEventLoop, phase: FatalErrorPhase, context: &str, error: &dyn std::fmt::Display) {
+        client::show_fatal_error(phase, context, error);
+        self.client_data_mut().failed = true;
+        event_loop.exit();
+    }
+
+    /// Ticks forever without a window or event loop, sleeping until each tick is due.
+    pub fn run_dedicated_server(&mut self) -> ! {
+        dedicated_server_only!(self.side, {
+            loop {
+                self.tick();
+                std::thread::sleep(self.tick_duration.saturating_sub(self.tick_accumulator));
+            }
+        })
+    }
 }
 
 impl winit::application::ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.side != Side::Client {
+            return
+        }
         let init_renderer = self.client_data().unwrap().window.is_none();
-        self.client_data_mut().window = Some(event_loop.create_window(self.attributes()).unwrap());
+        match event_loop.create_window(self.attributes()) {
+            Ok(window) => self.client_data_mut().window = Some(window),
+            Err(error) => return self.fail(event_loop, FatalErrorPhase::Startup, "failed to create window", &error),
+        }
         if init_renderer {
-            client::rendering::init(self, event_loop).expect("failed to initialize rendering")
+            let app_info = self.client_data().unwrap().app_info.clone();
+            if let Err(error) = client::rendering::init(self, event_loop, &app_info) {
+                self.fail(event_loop, FatalErrorPhase::Startup, "failed to initialize rendering", &error);
+            }
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        let ticks = self.tick();
+        // Nothing is left to render once initialization has failed, so wait for the event loop to exit.
+        if self.side != Side::Client || self.client_data().unwrap().failed {
+            return
+        }
+
+        let pending_resize = self.client_data().unwrap().pending_resize;
+        let paused = self.try_render_data().is_some_and(RenderData::is_paused);
+        // Resizes are deferred while paused, so that the swapchain is recreated once upon resuming.
+        let mut resized = false;
+        if !paused && pending_resize.is_some_and(|resized_at| resized_at.elapsed() >= constants::RESIZE_DEBOUNCE) {
+            self.client_data_mut().pending_resize = None;
+            if let Err(error) = client::rendering::resize(self) {
+                return self.fail(event_loop, FatalErrorPhase::Runtime, "error resizing swapchain", &error);
+            }
+            resized = true;
+        }
+
+        // Rendering only requests redraws by itself when polling, so request them upon anything worth rendering otherwise.
+        let control_flow = self.settings.control_flow;
+        if control_flow != ControlFlowStrategy::Poll && !paused && (resized || (control_flow == ControlFlowStrategy::WaitUntil && ticks > 0)) {
+            self.window().request_redraw();
         }
+        // Wake up in time to apply a pending resize, even if no events arrive.
+        let pending_resize = self.client_data().unwrap().pending_resize.map(|resized_at| resized_at + constants::RESIZE_DEBOUNCE);
+        let next_tick = self.last_tick + self.tick_duration.saturating_sub(self.tick_accumulator);
+        event_loop.set_control_flow(match control_flow {
+            ControlFlowStrategy::Poll => ControlFlow::Poll,
+            ControlFlowStrategy::Wait => pending_resize.map_or(ControlFlow::Wait, ControlFlow::WaitUntil),
+            ControlFlowStrategy::WaitUntil => ControlFlow::WaitUntil(pending_resize.map_or(next_tick, |pending_resize| pending_resize.min(next_tick))),
+        });
     }
 
     fn window_event(
@@ -90,18 +232,42 @@ impl winit::application::ApplicationHandler for App {
     ) {
         match event {
             WindowEvent::Destroyed => {
-                // Drop rendering data
-                let mut none = None;
-                core::mem::swap(&mut self.client_data_mut().render_data, &mut none);
-                drop(none);
+                if let Some(render_data) = self.client_data_mut().render_data.take() {
+                    render_data.shutdown();
+                }
+            },
+            WindowEvent::Resized(_) => {
+                // Defer recreating the swapchain until the size has settled.
+                self.client_data_mut().pending_resize = Some(Instant::now());
+            },
+            WindowEvent::Occluded(occluded) => {
+                let Some(render_data) = self.client_data_mut().render_data.as_mut() else {
+                    return
+                };
+                render_data.set_paused(occluded);
+                // Rendering stops requesting redraws while paused, so restart it.
+                if !occluded {
+                    self.window().request_redraw();
+                }
+            },
+            WindowEvent::KeyboardInput { event, .. } if event.state.is_pressed() && !event.repeat && event.physical_key == PhysicalKey::Code(constants::WIREFRAME_TOGGLE_KEY) => {
+                if let Some(render_data) = self.client_data_mut().render_data.as_mut() {
+                    render_data.toggle_wireframe();
+                }
             },
             WindowEvent::CloseRequested => {
                 event_loop.exit();
             },
             WindowEvent::RedrawRequested => {
-                client::rendering::begin_render(self).expect("error beginning rendering");
-                client::rendering::render_background(self).expect("error rendering background");
-                client::rendering::end_render(self).expect("error ending rendering");
+                // Rendering was never initialized if initialization failed, so there is nothing to render while the event loop exits.
+                if self.client_data().unwrap().failed {
+                    return
+                }
+                let interpolation_alpha = self.interpolation_alpha();
+                self.render_data_mut().interpolation_alpha = interpolation_alpha;
+                if let Err(error) = client::rendering::render(self) {
+                    self.fail(event_loop, FatalErrorPhase::Runtime, "error rendering", &error);
+                }
             },
             _ => (),
         }
@@ -109,21 +275,96 @@ impl winit::application::ApplicationHandler for App {
 }
 
 fn main() {
+    // Parse arguments
+    let Args { side, safe_mode } = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{error}");
+            eprintln!("usage: {} [client|server|dedicated_server] [--safe-mode]", constants::NAME.to_lowercase());
+            std::process::exit(2);
+        },
+    };
+
+    // Load settings
+    let mut settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(error) => {
+            // Logging isn't initialized yet, so the error is also printed for terminal users and dedicated servers, which have no message box.
+            eprintln!("failed to load settings: {error}");
+            if side != Side::DedicatedServer {
+                client::show_fatal_error(FatalErrorPhase::Startup, "failed to load settings", &error);
+            }
+            std::process::exit(1);
+        },
+    };
+    settings.safe_mode |= safe_mode;
+    if settings.safe_mode {
+        settings.apply_safe_mode();
+    }
+
     // Initialize logging
-    log::init().expect("logger initialization failed");
+    let _log_guard = log::init(settings.log_level).expect("logger initialization failed");
     log::hook_panic();
+    if settings.safe_mode {
+        warn!("Safe mode is active: forcing FIFO presentation, the draw image path, and validation layers, and disabling HDR, anisotropic filtering, and timeline semaphores");
+    }
+
+    if side == Side::DedicatedServer {
+        let mut app = App::new_dedicated_server(settings);
+        info!("Initializing with side `{}`", app.side());
+        app.run_dedicated_server();
+    }
 
     // Initialize event loop
-    let event_loop = EventLoop::new().unwrap();
-    event_loop.set_control_flow(ControlFlow::Poll);
+    let event_loop = match EventLoop::new() {
+        Ok(event_loop) => event_loop,
+        Err(error) => {
+            client::show_fatal_error(FatalErrorPhase::Startup, "failed to create event loop", &error);
+            drop(_log_guard);
+            std::process::exit(1);
+        },
+    };
+    // The control flow is updated every iteration of the event loop, since waiting strategies wait until a deadline.
+    event_loop.set_control_flow(match settings.control_flow {
+        ControlFlowStrategy::Poll => ControlFlow::Poll,
+        ControlFlowStrategy::Wait | ControlFlowStrategy::WaitUntil => ControlFlow::Wait,
+    });
 
     // Initialize window
     let window_attributes = WindowAttributes::default()
         .with_title(constants::NAME);
-    let mut app = App::new_client(window_attributes);
+    let mut app = App::new_client(settings, window_attributes, AppInfo::default());
 
     info!("Initializing with side `{}`", app.side());
 
     // Start event loop
-    event_loop.run_app(&mut app).unwrap();
+    if let Err(error) = event_loop.run_app(&mut app) {
+        client::show_fatal_error(FatalErrorPhase::Runtime, "error running event loop", &error);
+        app.client_data_mut().failed = true;
+    }
+    if app.client_data().unwrap().failed {
+        drop(app);
+        drop(_log_guard);
+        std::process::exit(1);
+    }
+}
+
+struct Args {
+    side: Side,
+    safe_mode: bool,
+}
+
+/// Parses the command-line arguments, where the side to run as defaults to the client.
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut side = None;
+    let mut safe_mode = false;
+    for arg in args {
+        match arg.as_str() {
+            "--safe-mode" => safe_mode = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown flag `{arg}`")),
+            _ if side.is_none() => side = Some(arg.parse().map_err(|error| format!("{error}"))?),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+    Ok(Args { side: side.unwrap_or(Side::Client), safe_mode })
 }
@@ -0,0 +1,36 @@
+//! # System Scheduler
+//! Runs game logic (systems) on the [`World`] for the current [`Side`].
+
+use std::time::Duration;
+
+use hecs::World;
+
+use crate::environment::Side;
+
+/// A system runs once per tick with the tick's delta time.
+pub type System = Box<dyn FnMut(&mut World, Duration)>;
+
+#[derive(Default)]
+pub struct Scheduler {
+    systems: Vec<(Side, System)>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a system that only runs on the given side.
+    /// Systems run in the order that they are registered.
+    pub fn add_system(&mut self, side: Side, system: impl FnMut(&mut World, Duration) + 'static) -> &mut Self {
+        self.systems.push((side, Box::new(system)));
+        self
+    }
+
+    /// Runs every system registered for `side`.
+    pub fn run(&mut self, side: Side, world: &mut World, dt: Duration) {
+        for (_, system) in self.systems.iter_mut().filter(|(system_side, _)| *system_side == side) {
+            system(world, dt);
+        }
+    }
+}
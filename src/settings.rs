@@ -0,0 +1,338 @@
+//! # Settings
+//! Runtime settings loaded from a config file.
+//!
+//! Settings are resolved in order of precedence: environment variables override the config file, which overrides the built-in defaults.
+
+use std::{fs, io, path::Path, str::FromStr, time::Duration};
+
+use ash::vk;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::constants;
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("I/O Error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("error parsing settings: {0}")]
+    ParseError(#[from] toml::de::Error),
+    #[error("invalid value for environment variable {0}: {1}")]
+    InvalidEnvVar(&'static str, String),
+}
+
+pub type SettingsResult<T> = Result<T, SettingsError>;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    /// Whether to enable the Vulkan validation layers and debug callback, which defaults to on in debug builds.
+    /// This is overridden by `SIGILL_VALIDATION`, or else `SIGILL_ENABLE_VALIDATION_LAYERS`.
+    pub enable_validation_layers: bool,
+    /// The preferred present mode, which falls back to similar modes if unsupported. See [`PresentMode::preferences`].
+    pub present_mode: PresentMode,
+    /// Whether to output in an HDR color space if the display supports it.
+    pub hdr: bool,
+    pub log_level: log::LevelFilter,
+    /// A case-insensitive substring of the name of the GPU to prefer over the highest-ranked one.
+    pub preferred_gpu: Option<String>,
+    /// Whether to rank discrete or integrated GPUs higher, unless [`Self::preferred_gpu`] is set.
+    pub power_preference: PowerPreference,
+    /// The number of game ticks per second.
+    pub tick_rate: u32,
+    /// How long to wait for the GPU before retrying, in milliseconds.
+    pub fence_timeout_ms: u64,
+    /// The maximum level of anisotropic filtering, which is disabled at 1 or below and clamped to the device limit.
+    pub max_anisotropy: f32,
+    /// Where each frame is rendered to, which is forced to [`RenderPath::DrawImage`] in safe mode.
+    pub render_path: RenderPath,
+    /// The resolution of the draw image relative to the window, which is scaled to fit the window upon presenting.
+    /// Values below 1 trade sharpness for performance. See [`Self::render_scale`].
+    pub render_scale: f32,
+    /// The filter used to scale the draw image to fit the window, which is visible when [`Self::render_scale`] isn't 1.
+    pub upscale_filter: UpscaleFilter,
+    /// How the event loop waits for events, which decides whether frames are rendered continuously.
+    pub control_flow: ControlFlowStrategy,
+    /// Whether to synchronize frames with a timeline semaphore rather than per-frame fences, if the device supports it.
+    pub timeline_semaphores: bool,
+    /// Whether to force the most conservative rendering path, as a fallback for unstable drivers. See [`Self::apply_safe_mode`].
+    pub safe_mode: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            enable_validation_layers: constants::ENABLE_VALIDATION_LAYERS,
+            present_mode: PresentMode::Mailbox,
+            hdr: false,
+            log_level: constants::LOG_LEVEL,
+            preferred_gpu: None,
+            power_preference: PowerPreference::HighPerformance,
+            tick_rate: constants::TICK_RATE,
+            fence_timeout_ms: Duration::from_nanos(constants::FENCE_TIMEOUT).as_millis() as u64,
+            max_anisotropy: 16.0,
+            render_path: RenderPath::DrawImage,
+            render_scale: 1.0,
+            upscale_filter: UpscaleFilter::Linear,
+            control_flow: ControlFlowStrategy::Poll,
+            timeline_semaphores: false,
+            safe_mode: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings from [`constants::SETTINGS_PATH`], using the defaults if the file is absent.
+    pub fn load() -> SettingsResult<Self> {
+        Self::load_from(constants::SETTINGS_PATH)
+    }
+
+    pub fn load_from(path: impl AsRef<Path>) -> SettingsResult<Self> {
+        let mut settings = match fs::read_to_string(path) {
+            Ok(source) => toml::from_str(&source)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e.into()),
+        };
+        settings.apply_env()?;
+        Ok(settings)
+    }
+
+    /// Overrides the settings that enable less stable features with their most conservative values.
+    /// Validation layers are enabled if they are installed, but their absence doesn't fail initialization in safe mode.
+    /// The renderer also ignores changes to [`RenderData::render_path`](crate::client::rendering::RenderData::render_path) and renders via the draw image while in safe mode.
+    pub fn apply_safe_mode(&mut self) {
+        self.render_path = RenderPath::DrawImage;
+        self.present_mode = PresentMode::Fifo;
+        self.enable_validation_layers = true;
+        self.hdr = false;
+        self.max_anisotropy = 1.0;
+        self.timeline_semaphores = false;
+    }
+
+    /// The render scale, clamped between [`constants::MIN_RENDER_SCALE`] and [`constants::MAX_RENDER_SCALE`].
+    pub fn render_scale(&self) -> f32 {
+        if self.render_scale.is_nan() {
+            return 1.0
+        }
+        self.render_scale.clamp(constants::MIN_RENDER_SCALE, constants::MAX_RENDER_SCALE)
+    }
+
+    /// The fixed duration of a game tick.
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.tick_rate.max(1)
+    }
+
+    /// Overrides settings with their accompanying environment variables.
+    fn apply_env(&mut self) -> SettingsResult<()> {
+        // `SIGILL_VALIDATION` is the shorthand for `SIGILL_ENABLE_VALIDATION_LAYERS`, and takes precedence if both are set.
+        if let Some(Toggle(value)) = env_var("SIGILL_ENABLE_VALIDATION_LAYERS")? {
+            self.enable_validation_layers = value;
+        }
+        if let Some(Toggle(value)) = env_var("SIGILL_VALIDATION")? {
+            self.enable_validation_layers = value;
+        }
+        if let Some(value) = env_var("SIGILL_PRESENT_MODE")? {
+            self.present_mode = value;
+        }
+        if let Some(Toggle(value)) = env_var("SIGILL_HDR")? {
+            self.hdr = value;
+        }
+        if let Some(value) = env_var("SIGILL_LOG_LEVEL")? {
+            self.log_level = value;
+        }
+        if let Some(value) = env_var("SIGILL_PREFERRED_GPU")? {
+            self.preferred_gpu = Some(value);
+        }
+        if let Some(value) = env_var("SIGILL_POWER_PREFERENCE")? {
+            self.power_preference = value;
+        }
+        if let Some(value) = env_var("SIGILL_TICK_RATE")? {
+            self.tick_rate = value;
+        }
+        if let Some(value) = env_var("SIGILL_FENCE_TIMEOUT_MS")? {
+            self.fence_timeout_ms = value;
+        }
+        if let Some(value) = env_var("SIGILL_MAX_ANISOTROPY")? {
+            self.max_anisotropy = value;
+        }
+        if let Some(value) = env_var("SIGILL_RENDER_PATH")? {
+            self.render_path = value;
+        }
+        if let Some(value) = env_var("SIGILL_RENDER_SCALE")? {
+            self.render_scale = value;
+        }
+        if let Some(value) = env_var("SIGILL_UPSCALE_FILTER")? {
+            self.upscale_filter = value;
+        }
+        if let Some(value) = env_var("SIGILL_CONTROL_FLOW")? {
+            self.control_flow = value;
+        }
+        if let Some(Toggle(value)) = env_var("SIGILL_TIMELINE_SEMAPHORES")? {
+            self.timeline_semaphores = value;
+        }
+        if let Some(Toggle(value)) = env_var("SIGILL_SAFE_MODE")? {
+            self.safe_mode = value;
+        }
+        Ok(())
+    }
+}
+
+fn env_var<T: FromStr>(name: &'static str) -> SettingsResult<Option<T>> {
+    match std::env::var(name) {
+        Ok(value) => value.parse().map(Some).map_err(|_| SettingsError::InvalidEnvVar(name, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// A boolean environment variable that also accepts `on`/`off`.
+struct Toggle(bool);
+
+impl FromStr for Toggle {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "on" | "true" | "1" => Ok(Self(true)),
+            "off" | "false" | "0" => Ok(Self(false)),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    Immediate,
+    Mailbox,
+    Fifo,
+    FifoRelaxed,
+}
+
+impl PresentMode {
+    /// The present modes to try in order of preference, which fall back to the closest behavior if this mode is unsupported.
+    pub fn preferences(self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            // Prefer tearing over added latency.
+            Self::Immediate => &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO],
+            Self::Mailbox => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO],
+            Self::Fifo => &[vk::PresentModeKHR::FIFO],
+            Self::FifoRelaxed => &[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO],
+        }
+    }
+}
+
+impl FromStr for PresentMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "immediate" => Ok(Self::Immediate),
+            "mailbox" => Ok(Self::Mailbox),
+            "fifo" => Ok(Self::Fifo),
+            "fifo_relaxed" => Ok(Self::FifoRelaxed),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How the event loop waits for events.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlFlowStrategy {
+    /// Run continuously, rendering as many frames as possible (e.g. for games).
+    Poll,
+    /// Sleep until an event arrives, only rendering in response to events (e.g. for editors).
+    Wait,
+    /// Sleep until an event arrives or the next tick is due, rendering once per tick.
+    WaitUntil,
+}
+
+impl FromStr for ControlFlowStrategy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "poll" => Ok(Self::Poll),
+            "wait" => Ok(Self::Wait),
+            "wait_until" => Ok(Self::WaitUntil),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where each frame is rendered to.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderPath {
+    /// Render to an HDR draw image, which is then blitted to the swapchain image.
+    #[default]
+    DrawImage,
+    /// Render directly to the swapchain image, which skips the blit but forgoes the HDR intermediate and the render scale.
+    /// The draw image's load op is ignored, since swapchain images don't retain their contents between frames.
+    Swapchain,
+}
+
+impl FromStr for RenderPath {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "draw_image" => Ok(Self::DrawImage),
+            "swapchain" => Ok(Self::Swapchain),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How to filter the draw image when scaling it to fit the window.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum UpscaleFilter {
+    /// Blend neighboring pixels, which looks smooth.
+    Linear,
+    /// Use the closest pixel, which keeps edges sharp (e.g. for pixel art).
+    Nearest,
+}
+
+impl FromStr for UpscaleFilter {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear" => Ok(Self::Linear),
+            "nearest" => Ok(Self::Nearest),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Which kind of GPU to prefer, like WebGPU's adapter power preference.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerPreference {
+    /// Prefer discrete GPUs.
+    HighPerformance,
+    /// Prefer integrated GPUs, which use less power (e.g. to save battery on laptops).
+    LowPower,
+}
+
+impl FromStr for PowerPreference {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "high_performance" => Ok(Self::HighPerformance),
+            "low_power" => Ok(Self::LowPower),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<UpscaleFilter> for vk::Filter {
+    fn from(upscale_filter: UpscaleFilter) -> Self {
+        match upscale_filter {
+            UpscaleFilter::Linear => vk::Filter::LINEAR,
+            UpscaleFilter::Nearest => vk::Filter::NEAREST,
+        }
+    }
+}